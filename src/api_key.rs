@@ -0,0 +1,116 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde_yaml::Value;
+
+/// Reads the API key from the first line of a file. Like ssh with private keys, the file must not be
+/// accessible by other users.
+pub fn read_api_key_file(path: &Path) -> Result<String, String> {
+    if let Some(mode) = accessible_by_others(path) {
+        return Err(format!("The API key file {} is accessible by other users (mode {:o}). Restrict it with: chmod 600 {}",
+                           path.display(), mode, path.display()));
+    }
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read the API key file {}: {}", path.display(), e))?;
+    first_line(&content).ok_or_else(|| format!("The API key file {} is empty", path.display()))
+}
+
+/// Runs a command like `pass show openai` and uses the first line of its output as the API key.
+/// Its stderr is passed through, so that password managers can ask for a passphrase.
+pub fn run_api_key_command(command: &str) -> Result<String, String> {
+    let mut process = if cfg!(windows) {
+        let mut process = Command::new("cmd");
+        process.arg("/C");
+        process
+    } else {
+        let mut process = Command::new("sh");
+        process.arg("-c");
+        process
+    };
+    let output = process.arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to run the apiKeyCommand `{}`: {}", command, e))?;
+    if !output.status.success() {
+        return Err(format!("The apiKeyCommand `{}` failed ({})", command, output.status));
+    }
+    first_line(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| format!("The apiKeyCommand `{}` printed nothing", command))
+}
+
+fn first_line(text: &str) -> Option<String> {
+    text.lines().map(|l| l.trim()).find(|l| !l.is_empty()).map(|l| l.to_string())
+}
+
+/// The permissions of the file if the group or others can read, write or execute it.
+#[cfg(unix)]
+pub fn accessible_by_others(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path).ok()?.permissions().mode() & 0o777;
+    (mode & 0o077 != 0).then_some(mode)
+}
+
+#[cfg(not(unix))]
+pub fn accessible_by_others(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// The permissions of the file if the group or others can read it.
+#[cfg(unix)]
+pub fn readable_by_others(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path).ok()?.permissions().mode() & 0o777;
+    (mode & 0o044 != 0).then_some(mode)
+}
+
+#[cfg(not(unix))]
+pub fn readable_by_others(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// Whether a config document sets `apiKey` anywhere, e.g. in `openAi` or in a profile.
+pub fn contains_api_key(value: &Value) -> bool {
+    match value {
+        Value::Mapping(mapping) => mapping.iter().any(|(key, value)| {
+            (key.as_str() == Some("apiKey") && !value.is_null()) || contains_api_key(value)
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_api_key_file_must_be_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("openai.key");
+        std::fs::write(&path, "\nsk-from-file\n").unwrap();
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let err = read_api_key_file(&path).unwrap_err();
+        assert!(err.contains("mode 644") && err.contains("chmod 600"), "{}", err);
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(read_api_key_file(&path).unwrap(), "sk-from-file");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_api_key_command() {
+        assert_eq!(run_api_key_command("printf 'sk-from-command\\nlogin: me\\n'").unwrap(), "sk-from-command");
+        assert!(run_api_key_command("exit 3").unwrap_err().contains("failed"));
+        assert!(run_api_key_command("true").unwrap_err().contains("printed nothing"));
+    }
+
+    #[test]
+    fn test_contains_api_key() {
+        let value: Value = serde_yaml::from_str("openAi:\n  apiKey: null\nprofiles:\n  work:\n    openAi:\n      apiKey: sk-1\n").unwrap();
+        assert!(contains_api_key(&value));
+        assert!(!contains_api_key(&serde_yaml::from_str("openAi:\n  apiKey: null\n").unwrap()));
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use globset::GlobBuilder;
+use ignore::WalkBuilder;
+
+use crate::context::{ContextBlock, PRIORITY_INPUT};
+use crate::redact::redact;
+use crate::tokens::{TokenEstimator, truncate, TruncationStrategy};
+
+/// How much of the files and directories passed via `--file` and `--dir` ends up in the prompt.
+#[derive(Debug, Clone)]
+pub struct AttachLimits {
+    /// Files larger than this are skipped.
+    pub max_file_bytes: u64,
+    /// The total number of tokens for all attached files, and the maximum for each directory listing.
+    pub max_tokens: usize,
+    /// Whether to remove likely secrets from the attached files.
+    pub redact: bool,
+}
+
+/// The context blocks of the attached files, and the files that were left out with the reason.
+#[derive(Debug, Default)]
+pub struct Attachments {
+    pub blocks: Vec<ContextBlock>,
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+/// Expands `--file` arguments to paths. Plain paths are taken as they are,
+/// glob patterns (e.g. `src/**/*.rs`) match the files that are not excluded by `.gitignore`.
+pub fn expand_file_patterns(patterns: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        if !is_glob(pattern) {
+            let path = PathBuf::from(pattern);
+            if !path.is_file() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not a file", pattern)));
+            }
+            paths.push(path);
+            continue;
+        }
+
+        let matches = glob_files(pattern)?;
+        if matches.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No files match {}", pattern)));
+        }
+        paths.extend(matches);
+    }
+    let mut seen = HashSet::new();
+    paths.retain(|p| seen.insert(p.clone()));
+    Ok(paths)
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+fn glob_files(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let glob = GlobBuilder::new(pattern.strip_prefix("./").unwrap_or(pattern))
+        .literal_separator(true)
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?
+        .compile_matcher();
+
+    // walk from the part of the pattern without wildcards, e.g. `src` for `src/**/*.rs`
+    let components = Path::new(pattern).components().collect::<Vec<_>>();
+    let literal = components.iter()
+        .take_while(|c| !is_glob(&c.as_os_str().to_string_lossy()))
+        .count();
+    let base = components[..literal].iter().collect::<PathBuf>();
+    let depth = (!pattern.contains("**")).then_some(components.len() - literal);
+
+    let walk_root = if base.as_os_str().is_empty() { PathBuf::from(".") } else { base };
+    let mut paths = Vec::new();
+    for entry in WalkBuilder::new(&walk_root).max_depth(depth).sort_by_file_name(|a, b| a.cmp(b)).build() {
+        let entry = entry.map_err(io::Error::other)?;
+        let path = entry.path();
+        let relative = path.strip_prefix(".").unwrap_or(path);
+        if entry.file_type().is_some_and(|t| t.is_file()) && glob.is_match(relative) {
+            paths.push(relative.to_path_buf());
+        }
+    }
+    Ok(paths)
+}
+
+/// Reads the files into fenced, language-tagged context blocks.
+/// Binary files and files above the size limit are skipped,
+/// and once the token limit is reached, the remaining files are shortened or left out.
+pub fn attach_files(paths: &[PathBuf], limits: &AttachLimits, estimator: &TokenEstimator) -> Attachments {
+    let mut attachments = Attachments::default();
+    let mut remaining = limits.max_tokens;
+    for path in paths {
+        let content = match read_text_file(path, limits.max_file_bytes) {
+            Ok(content) => content,
+            Err(reason) => {
+                attachments.skipped.push((path.clone(), reason));
+                continue;
+            }
+        };
+        if remaining == 0 {
+            attachments.skipped.push((path.clone(), "the size limit for attached files is reached".to_string()));
+            continue;
+        }
+        let content = if limits.redact { redact(&content) } else { content };
+        let content = truncate(content.trim_end(), remaining, TruncationStrategy::Head, estimator);
+        remaining = remaining.saturating_sub(estimator.count(&content));
+
+        attachments.blocks.push(ContextBlock {
+            name: path.display().to_string(),
+            content: format!("the content of the file {} is:\n```{}\n{}\n```",
+                             path.display(), fence_lang(path), content),
+            priority: PRIORITY_INPUT,
+        });
+    }
+    attachments
+}
+
+fn read_text_file(path: &Path, max_bytes: u64) -> Result<String, String> {
+    let size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    if size > max_bytes {
+        return Err(format!("it is larger than {} bytes", max_bytes));
+    }
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    if bytes.contains(&0) {
+        return Err("it is a binary file".to_string());
+    }
+    String::from_utf8(bytes).map_err(|_| "it is a binary file".to_string())
+}
+
+/// A listing of the directory up to the given depth, without the files excluded by `.gitignore`.
+pub fn directory_listing(dir: &Path, depth: usize, limits: &AttachLimits, estimator: &TokenEstimator)
+                         -> io::Result<ContextBlock> {
+    if !dir.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not a directory", dir.display())));
+    }
+    let mut lines = Vec::new();
+    for entry in WalkBuilder::new(dir).max_depth(Some(depth)).sort_by_file_name(|a, b| a.cmp(b)).build() {
+        let entry = entry.map_err(io::Error::other)?;
+        if entry.depth() == 0 {
+            continue;
+        }
+        let suffix = if entry.file_type().is_some_and(|t| t.is_dir()) { "/" } else { "" };
+        lines.push(format!("{}{}{}", "  ".repeat(entry.depth() - 1), entry.file_name().to_string_lossy(), suffix));
+    }
+    let listing = truncate(&lines.join("\n"), limits.max_tokens, TruncationStrategy::Head, estimator);
+    Ok(ContextBlock {
+        name: dir.display().to_string(),
+        content: format!("the directory {} contains:\n```\n{}\n```", dir.display(), listing),
+        priority: PRIORITY_INPUT,
+    })
+}
+
+/// The language tag of a fenced code block for the file.
+fn fence_lang(path: &Path) -> &'static str {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    match name.as_str() {
+        "Dockerfile" => return "dockerfile",
+        "Makefile" => return "makefile",
+        _ => {}
+    }
+    match path.extension().map(|e| e.to_string_lossy().to_lowercase()).as_deref() {
+        Some("rs") => "rust",
+        Some("py") => "python",
+        Some("js" | "mjs" | "cjs") => "javascript",
+        Some("ts") => "typescript",
+        Some("sh" | "bash") => "bash",
+        Some("zsh") => "zsh",
+        Some("fish") => "fish",
+        Some("ps1") => "powershell",
+        Some("rb") => "ruby",
+        Some("pl") => "perl",
+        Some("php") => "php",
+        Some("go") => "go",
+        Some("java") => "java",
+        Some("kt") => "kotlin",
+        Some("c" | "h") => "c",
+        Some("cpp" | "cc" | "hpp") => "cpp",
+        Some("cs") => "csharp",
+        Some("json") => "json",
+        Some("yml" | "yaml") => "yaml",
+        Some("toml") => "toml",
+        Some("md") => "markdown",
+        Some("html") => "html",
+        Some("css") => "css",
+        Some("sql") => "sql",
+        Some("xml") => "xml",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> AttachLimits {
+        AttachLimits { max_file_bytes: 1000, max_tokens: 1000, redact: true }
+    }
+
+    #[test]
+    fn test_files_become_fenced_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let code = dir.path().join("main.rs");
+        std::fs::write(&code, "fn main() {}\n").unwrap();
+        let binary = dir.path().join("image.png");
+        std::fs::write(&binary, [0x89, b'P', b'N', b'G', 0, 1]).unwrap();
+        let large = dir.path().join("large.txt");
+        std::fs::write(&large, "x".repeat(2000)).unwrap();
+
+        let attachments = attach_files(&[code.clone(), binary.clone(), large.clone()], &limits(),
+                                       &TokenEstimator::for_model("gpt-4o"));
+
+        assert_eq!(attachments.blocks.len(), 1);
+        assert!(attachments.blocks[0].content.ends_with("```rust\nfn main() {}\n```"));
+        let skipped = attachments.skipped.iter().map(|(p, _)| p.clone()).collect::<Vec<PathBuf>>();
+        assert_eq!(skipped, vec![binary, large]);
+    }
+
+    #[test]
+    fn test_directory_listing_respects_gitignore_and_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        std::fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+        std::fs::create_dir_all(dir.path().join("target")).unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "target\n").unwrap();
+        std::fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+        std::fs::write(dir.path().join("src/nested/deep.rs"), "").unwrap();
+
+        let block = directory_listing(dir.path(), 2, &limits(), &TokenEstimator::for_model("gpt-4o")).unwrap();
+
+        assert!(block.content.contains("src/\n  lib.rs\n  nested/\n```"), "{}", block.content);
+        assert!(!block.content.contains("target"));
+        assert!(!block.content.contains("deep.rs"));
+    }
+
+    #[test]
+    fn test_fence_lang() {
+        assert_eq!(fence_lang(Path::new("a/b.py")), "python");
+        assert_eq!(fence_lang(Path::new("Dockerfile")), "dockerfile");
+        assert_eq!(fence_lang(Path::new("notes")), "");
+    }
+}
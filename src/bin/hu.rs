@@ -1,17 +1,52 @@
+use std::collections::BTreeMap;
 use std::io;
+use std::io::{BufRead, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, OnceLock};
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap::error::ErrorKind;
 use colored::*;
 use houston::{
+    attach::{attach_files, AttachLimits, directory_listing, expand_file_patterns},
+    check::{check_syntax, SyntaxCheck},
     config,
     generator,
-    config::{ApiKey, StrictUserConfig},
-    context::{ContextCall, does_default_ctxt_exist, read_and_evaluate_context_file_by_name},
-    generator::create_chat_prompt,
-    runner::SimpleScriptRunner,
+    config::{ApiKey, ApiKeySource, ContextBudgetConfig, ExamplesConfig, HttpConfig, InputConfig, ProvidersConfig, SamplingParameters},
+    config::{DEFAULT_OPEN_AI_BASE_URL, EnvironmentConfig, obfuscate_api_key, StrictUserConfig},
+    context::{ContextBlock, ContextCall, does_ctxt_exist, does_default_ctxt_exist, read_and_evaluate_context_file_by_name},
+    context::{PRIORITY_BUILTIN, PRIORITY_DEFAULT, PRIORITY_INPUT, PRIORITY_NAMED},
+    diff::{diff_lines, format_diff},
+    environment::{default_cache_path, Environment},
+    examples::{Example, load_examples, select_examples},
+    generator::{create_chat_prompt, Repair, ScriptSpecification, Turn},
+    http::HttpClient,
+    integration::{integration_script, SUPPORTED_SHELLS},
+    highlight::{highlight, Syntax},
+    history::last_command,
+    config_file::{self, ConfigProblem},
+    layers::{env_layers, format_problems, format_value, leaf_paths, MergedConfig, parse_scalar},
+    interpreter::{Interpreter, InterpreterRegistry, parse_shebang},
+    migration::{backup, CONFIG_VERSION, migrate_text, migrate_value, migrations, version_of, write_migrated},
+    profile::{PROFILE_ENV_VAR, profiles, select_profile},
+    prompt::PromptTemplate,
+    provider::ProviderRegistry,
+    redact::redact,
+    repl::{ContextChange, parse_input, read_cwd, REPL_HELP, ReplCommand, ReplInput, Session, with_cwd_tracking},
+    tokens::{apply_budget, context_window, TokenEstimator, truncate, TruncationStrategy},
+    usage::{ModelPrice, parse_duration, summarize, TokenUsage, UsageGrouping, UsageLedger, UsageRecord},
+    runner::{save_script, SimpleScriptRunner},
+    setup::{available_shells, check_connection, config_entries, default_context, DetectedShell, KeySource, Preference},
+    setup::{create_private_file, PREFERENCES, SetupAnswers, write_api_key_file},
+    api_key::run_api_key_command,
+    provider::find_on_path,
 };
 use houston::generator::ScriptGenerator;
 use houston::runner::ScriptRunner;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
 
 
 #[derive(Parser, Debug)]
@@ -23,9 +58,21 @@ struct CliOptions {
     #[arg(short, long, default_value_t = false, help = "Don't run the generated program, just print it to stdout")]
     dry: bool,
 
-    #[arg(short, long, default_value_t = false, help = "Print verbose output")]
+    #[arg(short, long, global = true, default_value_t = false, help = "Print verbose output")]
     verbose: bool,
 
+    #[arg(long, default_value_t = false,
+    help = "Only print a single generated command line for an interactive shell (used by the shell integration), \
+    without any decoration, questions or running it")]
+    print_only: bool,
+
+    #[arg(long, default_value_t = false,
+    help = "Print only the script to stdout and everything else to stderr (default if stdout is not a terminal)")]
+    raw: bool,
+
+    #[arg(short, long, help = "Save the script to this file (executable) instead of running it")]
+    output: Option<PathBuf>,
+
     // config overrides
     #[arg(short, long, help = "The shell to use to run the generated script")]
     shell: Option<String>,
@@ -35,50 +82,296 @@ struct CliOptions {
 
     #[arg(short, long, help = "The OpenAI model to use")]
     model: Option<String>,
+
+    #[arg(long, global = true, help = "Name of a config profile to use (default: $HOUSTON_PROFILE or the one matching the directory)")]
+    profile: Option<String>,
+
+    #[arg(long, help = "Name of a model preset from the config (e.g. fast, creative)")]
+    preset: Option<String>,
+
+    #[arg(long, help = "Sampling temperature (0-2)")]
+    temperature: Option<f32>,
+
+    #[arg(long, help = "Nucleus sampling probability mass (0-1)")]
+    top_p: Option<f32>,
+
+    #[arg(long, help = "Maximum number of tokens to generate")]
+    max_tokens: Option<u32>,
+
+    #[arg(long, help = "Sequence at which the model stops generating (repeatable)")]
+    stop: Vec<String>,
+
+    #[arg(long, help = "Seed for deterministic sampling")]
+    seed: Option<i64>,
+
+    #[arg(long, help = "Presence penalty (-2 to 2)")]
+    presence_penalty: Option<f32>,
+
+    #[arg(long, help = "Frequency penalty (-2 to 2)")]
+    frequency_penalty: Option<f32>,
     // end config overrides
+    #[arg(long, default_value_t = 1, help = "Number of candidate scripts to generate and pick from")]
+    candidates: u32,
+
+    #[arg(long, default_value_t = false, help = "If the script fails, ask the model to fix it and try again")]
+    fix: bool,
+
+    #[arg(long, default_value_t = 3, help = "Maximum number of fix attempts with --fix")]
+    fix_attempts: u32,
+
     #[arg(short, long, help = "Names of the context files to use")]
     context: Vec<String>,
 
-    #[arg(help = "The instruction for the Assistant")]
+    #[arg(long, help = "Add the content of this file as context (repeatable, supports globs like 'src/**/*.rs')")]
+    file: Vec<String>,
+
+    #[arg(long, help = "Add a listing of this directory as context (repeatable)")]
+    dir: Vec<PathBuf>,
+
+    #[arg(long, default_value_t = 2, help = "How deep to list the directories of --dir")]
+    depth: usize,
+
+    #[arg(short, long, help = "Read the instruction from this file")]
+    instruction_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(help = "The instruction for the Assistant (- to read it from stdin)")]
     instruction: Vec<String>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "Show and change the configuration")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    #[command(about = "Work with context files")]
+    Ctx {
+        #[command(subcommand)]
+        command: CtxCommand,
+    },
+    #[command(about = "Propose a corrected version of a failed command")]
+    Fix {
+        #[arg(long, default_value_t = false, help = "Read the error output from stdin instead of running the command again")]
+        paste: bool,
+
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true,
+        help = "The failed command (default: the last command from the shell history)")]
+        command: Vec<String>,
+    },
+    #[command(about = "Print the shell integration code that binds Ctrl-G to generate a command from the current line")]
+    Init {
+        #[arg(value_parser = SUPPORTED_SHELLS)]
+        shell: String,
+    },
+    #[command(about = "Set up the shells, the API endpoint and key, and a starter default.ctxt")]
+    Setup,
+    #[command(about = "Start an interactive session (the same as running hu without an instruction)")]
+    Shell,
+    #[command(about = "Show token usage and estimated cost")]
+    Usage {
+        #[arg(long, help = "Only include invocations within this period, with a unit (e.g. 12h, 7d, 4w)")]
+        since: Option<String>,
+
+        #[arg(long, value_parser = ["model", "context"], help = "Group the usage by model or context")]
+        by: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    #[command(about = "Print the effective configuration, merged from all config files and HOUSTON_* variables")]
+    Show {
+        #[arg(long, default_value_t = false, help = "Print every key with the place its value comes from")]
+        origin: bool,
+    },
+    #[command(about = "Print the effective value of a key, e.g. openAi.model")]
+    Get {
+        key: String,
+    },
+    #[command(about = "Set a key in the user config (or the project config)")]
+    Set {
+        key: String,
+        value: String,
+
+        #[arg(long, default_value_t = false, help = "Change the project config (.houston/config.yml)")]
+        project: bool,
+    },
+    #[command(about = "Remove a key from the user config (or the project config)")]
+    Unset {
+        key: String,
+
+        #[arg(long, default_value_t = false, help = "Change the project config (.houston/config.yml)")]
+        project: bool,
+    },
+    #[command(about = "Open the user config (or the project config) in $EDITOR and validate it afterwards")]
+    Edit {
+        #[arg(long, default_value_t = false, help = "Edit the project config (.houston/config.yml)")]
+        project: bool,
+    },
+    #[command(about = "Check all config files and HOUSTON_* variables for unknown keys and invalid values")]
+    Validate,
+    #[command(about = "List the profiles and show which one is used")]
+    Profiles,
+    #[command(about = "Upgrade the user config (or the project config) to the current config version")]
+    Migrate {
+        #[arg(long, default_value_t = false, help = "Only show the changes, don't write them")]
+        dry_run: bool,
+
+        #[arg(long, default_value_t = false, help = "Migrate the project config (.houston/config.yml)")]
+        project: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CtxCommand {
+    #[command(about = "Evaluate context files and print the result with token counts")]
+    Render {
+        #[arg(help = "Names of the context files to render (default: the default context file)")]
+        context: Vec<String>,
+    },
+}
+
 #[derive(Debug)]
 struct Application {
     run_mode: config::RunMode,
     verbose: bool,
+    print_only: bool,
     shell: String,
+    /// Whether the shell was given on the command line (and therefore wins over a shebang).
+    shell_explicit: bool,
     context_shell: String,
+    interpreters: InterpreterRegistry,
+    shebang_policy: config::ShebangPolicy,
     model: String,
+    sampling: SamplingParameters,
+    candidates: u32,
+    fix: bool,
+    fix_attempts: u32,
+    output: Option<PathBuf>,
     context: Vec<String>,
     instruction: Vec<String>,
-    open_ai_api_key: ApiKey,
+    instruction_file: Option<PathBuf>,
+    files: Vec<String>,
+    dirs: Vec<PathBuf>,
+    depth: usize,
+    input: InputConfig,
+    providers: ProvidersConfig,
+    environment: EnvironmentConfig,
+    /// `environment.cacheTtl` in seconds.
+    environment_cache_ttl: u64,
+    open_ai_api_key: ApiKeySource,
+    /// The resolved key, so that `apiKeyCommand` runs at most once per session.
+    resolved_api_key: OnceLock<ApiKey>,
+    open_ai_base_url: String,
+    http: HttpConfig,
+    context_budget: ContextBudgetConfig,
+    pricing: BTreeMap<String, ModelPrice>,
+    examples: ExamplesConfig,
+    /// In an interactive session: the file that scripts write their final working directory to.
+    session_cwd_file: Option<PathBuf>,
 }
 
 impl Application {
-    fn from(cli_options: CliOptions, user_config: StrictUserConfig) -> Self {
-        Application {
+    fn from(cli_options: CliOptions, user_config: StrictUserConfig) -> Result<Self, String> {
+        // precedence: config < preset < cli options
+        let mut model = user_config.open_ai.model;
+        let mut sampling = user_config.open_ai.sampling;
+        if let Some(name) = &cli_options.preset {
+            let preset = user_config.presets.get(name).ok_or_else(|| format!(
+                "Unknown preset '{}'. Available presets: {}",
+                name, user_config.presets.keys().cloned().collect::<Vec<String>>().join(", ")))?;
+            model = preset.model.clone().unwrap_or(model);
+            sampling = sampling.merged_with(&preset.sampling);
+        }
+        let sampling = sampling.merged_with(&SamplingParameters {
+            temperature: cli_options.temperature,
+            top_p: cli_options.top_p,
+            max_tokens: cli_options.max_tokens,
+            stop: Some(cli_options.stop).filter(|s| !s.is_empty()),
+            seed: cli_options.seed,
+            presence_penalty: cli_options.presence_penalty,
+            frequency_penalty: cli_options.frequency_penalty,
+        });
+
+        Ok(Application {
             run_mode: if cli_options.force {
                 config::RunMode::Force
             } else if cli_options.dry {
                 if cli_options.force {
-                    println!("Warning: --force and --dry are both set. --force will be ignored.");
+                    eprintln!("Warning: --force and --dry are both set. --force will be ignored.");
                 }
                 config::RunMode::Dry
             } else {
                 user_config.default_run_mode
             },
             verbose: cli_options.verbose,
+            print_only: cli_options.print_only,
+            shell_explicit: cli_options.shell.is_some(),
             shell: cli_options.shell.unwrap_or(user_config.default_shell),
             context_shell: cli_options.context_shell.unwrap_or(user_config.default_context_shell),
-            model: cli_options.model.unwrap_or(user_config.open_ai.model),
+            interpreters: InterpreterRegistry::with_overrides(&user_config.interpreters),
+            shebang_policy: user_config.shebang_policy,
+            model: cli_options.model.unwrap_or(model),
+            sampling,
+            candidates: cli_options.candidates,
+            fix: cli_options.fix,
+            fix_attempts: cli_options.fix_attempts,
+            output: cli_options.output,
             context: cli_options.context,
             instruction: cli_options.instruction,
+            instruction_file: cli_options.instruction_file,
+            files: cli_options.file,
+            dirs: cli_options.dir,
+            depth: cli_options.depth,
+            input: user_config.input,
+            providers: user_config.providers,
+            environment_cache_ttl: parse_duration(&user_config.environment.cache_ttl)
+                .map_err(|err| format!("Invalid environment.cacheTtl: {}", err))?,
+            environment: user_config.environment,
             open_ai_api_key: user_config.open_ai.api_key,
-        }
+            resolved_api_key: OnceLock::new(),
+            open_ai_base_url: user_config.open_ai.base_url,
+            http: user_config.http,
+            context_budget: user_config.context_budget,
+            pricing: user_config.pricing,
+            examples: user_config.examples,
+            session_cwd_file: None,
+        })
     }
 }
 
+/// In raw mode, stdout only gets the script (or the output of running it). Everything else goes to stderr.
+static RAW_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+fn raw_output() -> bool {
+    RAW_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Like `println!`, but prints to stderr in raw mode.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if raw_output() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Only the end of a failing script's stderr is sent back to the model.
+const MAX_STDERR_CHARS: usize = 4000;
+
+/// Everything needed to ask the model for another version of the script.
+struct Conversation<'a> {
+    generator: &'a dyn ScriptGenerator,
+    spec: ScriptSpecification,
+    context_names: Vec<String>,
+}
+
 fn os_name() -> String {
     let os = std::env::consts::OS;
     os.to_string()
@@ -87,7 +380,7 @@ fn os_name() -> String {
 impl Application {
     fn print_verbose(&self, message: &str) {
         if self.verbose {
-            println!("{}", message.bright_black());
+            say!("{}", message.bright_black());
         }
     }
 
@@ -98,7 +391,417 @@ impl Application {
         }
     }
 
-    fn run(&self) {
+    /// Evaluates the given context calls: context files, or built-in providers for names starting with `@`.
+    /// Exits the process if a context file cannot be evaluated.
+    fn evaluate_context_calls(&self, context_calls: &[ContextCall]) -> Vec<ContextBlock> {
+        context_calls.iter().map(|c| {
+            let providers = ProviderRegistry::builtin(&self.providers);
+            let result = match c.name.strip_prefix('@') {
+                Some(provider) => providers.provide(provider, &c.args_as_str_vec()),
+                None => read_and_evaluate_context_file_by_name(
+                    &c.name, &self.interpreters.resolve(&self.context_shell), &c.args_as_str_vec(), providers),
+            };
+
+            let content = result.unwrap_or_else(|err| {
+                eprintln!("Failed to read context file {}: {}", c.name, err);
+                std::process::exit(1);
+            });
+
+            ContextBlock {
+                name: c.name.clone(),
+                content,
+                priority: if c.name == "default" { PRIORITY_DEFAULT } else { PRIORITY_NAMED },
+            }
+        }).collect()
+    }
+
+    /// Number of tokens the context blocks may use, after subtracting the rest of the prompt.
+    /// The token counts are estimated, so the budget keeps a safety margin.
+    fn context_token_budget(&self, base_prompt: &str, estimator: &TokenEstimator) -> usize {
+        let max_tokens = self.context_budget.max_tokens.unwrap_or_else(|| {
+            context_window(&self.model).saturating_sub(self.context_budget.reserved_completion_tokens)
+        });
+        estimator.conservative(max_tokens).saturating_sub(estimator.count(base_prompt))
+    }
+
+    /// Without `--shell`, a shebang naming a known interpreter picks it.
+    /// If the shebang conflicts with an explicit `--shell`, the shebang policy decides.
+    fn interpreter_for(&self, script: &str) -> Interpreter {
+        let requested = self.interpreters.resolve(&self.shell);
+        let declared = match self.interpreters.find_by_shebang(script) {
+            // compared by entry, `--shell /opt/homebrew/bin/bash` does not conflict with `#!/bin/bash`
+            Some(declared) if Some(declared) != self.interpreters.find(&self.shell) => declared.clone(),
+            _ => return requested,
+        };
+        if !self.shell_explicit {
+            return declared;
+        }
+
+        let declared_name = parse_shebang(script).unwrap_or_default();
+        eprintln!("{}", format!("Warning: the script's shebang asks for {}, but the shell is {}",
+                                declared_name, self.shell).yellow());
+        let use_declared = match self.shebang_policy {
+            config::ShebangPolicy::Always => true,
+            config::ShebangPolicy::Never => false,
+            config::ShebangPolicy::Ask if self.run_mode == config::RunMode::Ask =>
+                ask_yes_no(&format!("Do you want to run it with {} instead? (y/n)", declared_name)),
+            config::ShebangPolicy::Ask => false,
+        };
+        if use_declared { declared } else { requested }
+    }
+
+    fn record_usage(&self, usage: TokenUsage, context_names: &[String]) {
+        let record = UsageRecord::new(&self.model, usage, &self.pricing, context_names.to_vec());
+        self.print_verbose(&format!("Tokens: {} prompt, {} completion, estimated cost: {}",
+                                    record.usage.prompt_tokens, record.usage.completion_tokens,
+                                    record.cost.map_or("unknown".to_string(), |c| format!("${:.4}", c))));
+        if let Err(err) = UsageLedger::default_location().append(&record) {
+            eprintln!("Failed to write usage ledger: {}", err);
+        }
+    }
+
+    /// Sends the script and its problem back to the model and returns the corrected script.
+    /// The repair stays part of the conversation, so later repairs see the earlier attempts.
+    fn repair(&self, conversation: &mut Conversation, script: &str, problem: String) -> Option<String> {
+        say!("Asking for a corrected script...");
+        self.print_verbose(&format!("Problem: {}", problem));
+        conversation.spec.repairs.push(Repair {
+            script: script.to_string(),
+            problem,
+        });
+
+        match conversation.generator.generate(&conversation.spec) {
+            Ok(generation) => {
+                self.record_usage(generation.usage, &conversation.context_names);
+                generation.scripts.into_iter().next()
+            }
+            Err(err) => {
+                eprintln!("Failed to repair script: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Offers one repair round if the syntax check of the script failed (in force mode without asking).
+    /// Returns the repaired script, if any.
+    fn offer_syntax_repair(&self, conversation: &mut Conversation, script: &str,
+                           checks: &[SyntaxCheck]) -> Option<String> {
+        let problem = syntax_problem(checks)?;
+        let repair = self.run_mode == config::RunMode::Force
+            || ask_yes_no("The syntax check failed. Do you want to send the error to the model for a repair? (y/n)");
+        if !repair {
+            return None;
+        }
+        self.repair(conversation, script, problem)
+    }
+
+    fn select_examples(&self, instruction: &str, estimator: &TokenEstimator) -> Vec<Example> {
+        if !self.examples.enabled {
+            return vec![];
+        }
+        let examples = load_examples(&self.shell).unwrap_or_else(|err| {
+            eprintln!("Failed to read examples: {}", err);
+            std::process::exit(1);
+        });
+        let selected = select_examples(&examples, instruction, self.examples.max_count,
+                                       self.examples.max_tokens, estimator);
+        self.print_verbose(&format!("Examples: {} of {} selected", selected.len(), examples.len()));
+        selected
+    }
+
+    fn run(&mut self) {
+        let from_stdin = self.instruction == ["-"];
+        let mut instruction = if from_stdin {
+            read_stdin("the instruction")
+        } else {
+            self.instruction.join(" ")
+        };
+        if let Some(path) = &self.instruction_file {
+            let content = std::fs::read_to_string(path).unwrap_or_else(|err| {
+                eprintln!("Failed to read instruction file {}: {}", path.display(), err);
+                std::process::exit(1);
+            });
+            if !instruction.is_empty() {
+                instruction.push_str("\n\n");
+            }
+            instruction.push_str(content.trim());
+        }
+        let mut instruction = instruction.trim().to_string();
+        if instruction.is_empty() && io::stdin().is_terminal() {
+            self.repl();
+            return;
+        } else if instruction.is_empty() {
+            instruction = "print Hello World".to_string();
+        }
+
+        let mut blocks = vec![];
+        // stdin is either the instruction or extra context, e.g. `cat error.log | hu fix this`
+        if !from_stdin {
+            if let Some(block) = piped_stdin().and_then(|input| self.stdin_block(&input)) {
+                blocks.push(block);
+            }
+        }
+        self.generate_and_run(instruction, blocks, &[]).unwrap_or_else(|err| {
+            eprintln!("Failed to generate script: {}", err);
+            std::process::exit(1);
+        });
+    }
+
+    /// The interactive session: instructions are generated and run one after another, with the earlier ones
+    /// as conversation, and the working directory follows the scripts.
+    fn repl(&mut self) {
+        let mut editor = DefaultEditor::new().unwrap_or_else(|err| {
+            eprintln!("Failed to start the interactive session: {}", err);
+            std::process::exit(1);
+        });
+        let history_path = config::get_houston_dir().join("repl_history");
+        // there is no history on the first start
+        let _ = editor.load_history(&history_path);
+        let cwd_file = tempfile::Builder::new().prefix("houston-cwd").tempfile()
+            .map(|file| file.into_temp_path())
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to create a temporary file: {}", err);
+                std::process::exit(1);
+            });
+        self.session_cwd_file = Some(cwd_file.to_path_buf());
+        let mut session = Session::new(std::mem::take(&mut self.context));
+
+        say!("Type an instruction, or :help for the commands. Press Ctrl-D to leave.");
+        loop {
+            let line = match editor.readline(&repl_prompt()) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(err) => {
+                    eprintln!("Failed to read the input: {}", err);
+                    break;
+                }
+            };
+            if !line.trim().is_empty() {
+                let _ = editor.add_history_entry(line.trim());
+            }
+            match parse_input(&line) {
+                Ok(ReplInput::Empty) => {}
+                Ok(ReplInput::Instruction(instruction)) => {
+                    self.context = session.context.clone();
+                    match self.generate_and_run(instruction.clone(), vec![], &session.turns) {
+                        // declined scripts are not remembered, the model would take them as run
+                        Ok(Some(script)) => session.remember(instruction, script),
+                        Ok(None) => {}
+                        Err(err) => eprintln!("Failed to generate script: {}", err),
+                    }
+                }
+                Ok(ReplInput::Command(command)) => {
+                    if !self.repl_command(command, &mut session) {
+                        break;
+                    }
+                }
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        if let Err(err) = editor.save_history(&history_path) {
+            eprintln!("Failed to save the history to {}: {}", history_path.display(), err);
+        }
+    }
+
+    /// Runs a `:` command of the interactive session. Returns false to leave the session.
+    fn repl_command(&mut self, command: ReplCommand, session: &mut Session) -> bool {
+        match command {
+            ReplCommand::Context(changes) => {
+                for change in &changes {
+                    if let ContextChange::Add(call) = change {
+                        if let Err(err) = self.check_context(&ContextCall::parse(call).name) {
+                            eprintln!("{}", err);
+                            return true;
+                        }
+                    }
+                }
+                session.change_context(&changes);
+                let context = if session.context.is_empty() { "none".to_string() } else { session.context.join(", ") };
+                say!("Contexts: {}", context);
+            }
+            ReplCommand::Shell(shell) => {
+                if let Some(shell) = shell {
+                    self.shell = shell;
+                    self.shell_explicit = true;
+                }
+                say!("Shell: {}", self.shell);
+            }
+            ReplCommand::Model(model) => {
+                if let Some(model) = model {
+                    self.model = model;
+                }
+                say!("Model: {}", self.model);
+            }
+            ReplCommand::Mode(run_mode) => {
+                if let Some(run_mode) = run_mode {
+                    self.run_mode = run_mode;
+                }
+                say!("Run mode: {}", format!("{:?}", self.run_mode).to_lowercase());
+            }
+            ReplCommand::Forget => {
+                session.turns.clear();
+                say!("Forgot the earlier instructions.");
+            }
+            ReplCommand::Help => say!("{}", REPL_HELP),
+            ReplCommand::Quit => return false,
+        }
+        true
+    }
+
+    /// Checks that a context file or provider exists before it is added to the session.
+    fn check_context(&self, name: &str) -> Result<(), String> {
+        match name.strip_prefix('@') {
+            Some(provider) => {
+                let providers = ProviderRegistry::builtin(&self.providers);
+                if providers.names().contains(&provider) {
+                    Ok(())
+                } else {
+                    Err(format!("Unknown provider @{}. Available providers: {}", provider,
+                                providers.names().iter().map(|n| format!("@{}", n)).collect::<Vec<String>>().join(", ")))
+                }
+            }
+            None if does_ctxt_exist(name) => Ok(()),
+            None => Err(format!("There is no context file {}.ctxt in {}", name, config::get_houston_dir().display())),
+        }
+    }
+
+    /// The files and directory listings passed via `--file` and `--dir`.
+    fn attachment_blocks(&self) -> Vec<ContextBlock> {
+        let limits = AttachLimits {
+            max_file_bytes: self.input.max_file_bytes,
+            max_tokens: self.input.files_max_tokens,
+            redact: self.input.redact,
+        };
+        let estimator = TokenEstimator::for_model(&self.model);
+        let paths = expand_file_patterns(&self.files).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+
+        let attachments = attach_files(&paths, &limits, &estimator);
+        for (path, reason) in &attachments.skipped {
+            eprintln!("Warning: skipping {} because {}", path.display(), reason);
+        }
+        let mut blocks = attachments.blocks;
+        for dir in &self.dirs {
+            blocks.push(directory_listing(dir, self.depth, &limits, &estimator).unwrap_or_else(|err| {
+                eprintln!("Failed to list {}: {}", dir.display(), err);
+                std::process::exit(1);
+            }));
+        }
+        blocks
+    }
+
+    /// Piped input as a context block, redacted and shortened to the configured size.
+    fn stdin_block(&self, input: &str) -> Option<ContextBlock> {
+        if input.trim().is_empty() {
+            return None;
+        }
+        let input = if self.input.redact { redact(input) } else { input.to_string() };
+        let estimator = TokenEstimator::for_model(&self.model);
+        let input = truncate(input.trim_end(), self.input.stdin_max_tokens, TruncationStrategy::Middle, &estimator);
+        Some(ContextBlock {
+            name: "stdin".to_string(),
+            content: format!("the following input was piped to the command:\n```\n{}\n```", input),
+            priority: PRIORITY_INPUT,
+        })
+    }
+
+    /// Asks for a corrected version of a failed command: the given one, or else the last one from the shell history.
+    /// The error output is captured by running the command again, or pasted by the user.
+    fn fix(&self, command: &[String], paste: bool) {
+        let command = if command.is_empty() {
+            match last_command(&self.shell) {
+                Ok(Some(command)) => command,
+                Ok(None) => {
+                    eprintln!("Could not find a previous command in the {} history. Pass it as an argument: hu fix <command>",
+                              self.shell);
+                    std::process::exit(1);
+                }
+                Err(err) => {
+                    eprintln!("Failed to read the shell history: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            command.join(" ")
+        };
+        if !self.print_only {
+            say!("Failed command: {}", command.white());
+        }
+
+        // the failed command may have side effects, so it is never run again without asking, not even in force mode;
+        // print-only mode never asks questions, so it reads the error output like --paste
+        let rerun = !paste && match self.run_mode {
+            config::RunMode::Ask | config::RunMode::Force if self.print_only => false,
+            config::RunMode::Ask | config::RunMode::Force =>
+                ask_yes_no("Do you want to run it again to capture its error output? (y/n)"),
+            config::RunMode::Dry => false,
+        };
+
+        let (exit_code, stderr) = if rerun {
+            let runner = SimpleScriptRunner::with_interpreter(self.interpreters.resolve(&self.shell)).capturing_stderr();
+            let outcome = runner.run_script(&command, &[], None).unwrap_or_else(|err| {
+                eprintln!("Failed to run the command: {}", err);
+                std::process::exit(1);
+            });
+            if outcome.success() {
+                say!("The command succeeded this time, there is nothing to fix.");
+                return;
+            }
+            (outcome.exit_code, outcome.stderr)
+        } else {
+            if io::stdin().is_terminal() && !self.print_only {
+                say!("Paste the error output, then press Ctrl-D (Ctrl-Z and Enter on Windows):");
+            }
+            let mut stderr = String::new();
+            if let Err(err) = io::stdin().read_to_string(&mut stderr) {
+                eprintln!("Failed to read the error output: {}", err);
+                std::process::exit(1);
+            }
+            (None, stderr)
+        };
+
+        let mut failure = format!("this command failed: {}", command);
+        if let Some(exit_code) = exit_code {
+            failure.push_str(&format!("\nit exited with code {}", exit_code));
+        }
+        let blocks = vec![
+            ContextBlock {
+                name: "failed command".to_string(),
+                content: failure,
+                priority: PRIORITY_BUILTIN,
+            },
+            ContextBlock {
+                name: "error output".to_string(),
+                content: format!("its error output was:\n{}", tail(stderr.trim_end(), MAX_STDERR_CHARS)),
+                priority: PRIORITY_NAMED,
+            },
+        ];
+        let instruction = format!("Correct the failed command `{}` so that it does what was intended", command);
+        self.generate_and_run(instruction, blocks, &[]).unwrap_or_else(|err| {
+            eprintln!("Failed to generate script: {}", err);
+            std::process::exit(1);
+        });
+    }
+
+    /// The API key, resolved the first time it is needed, so that local commands like `hu ctx render`
+    /// neither need a key nor run `apiKeyCommand`.
+    fn api_key(&self) -> Result<ApiKey, String> {
+        if let Some(key) = self.resolved_api_key.get() {
+            return Ok(key.clone());
+        }
+        let key = self.open_ai_api_key.resolve()?;
+        Ok(self.resolved_api_key.get_or_init(|| key).clone())
+    }
+
+    /// Generates a script for the instruction, with the context files, the given extra context blocks
+    /// and the earlier turns of the session, and runs it according to the run mode.
+    /// Returns the last script that was run (or saved, or printed with `--print-only`),
+    /// `None` if it was declined or only shown.
+    fn generate_and_run(&self, instruction: String, extra_blocks: Vec<ContextBlock>,
+                        history: &[Turn]) -> Result<Option<String>, String> {
         self.print_verbose(&format!("{:?}", self));
 
         let mut context_calls = self.context.iter().map(|s| ContextCall::parse(s)).collect::<Vec<ContextCall>>();
@@ -113,89 +816,1100 @@ impl Application {
         }
 
         let context_calls = context_calls;
+        let api_key = self.api_key()?;
 
-
-        let mut instruction = self.instruction.join(" ");
-        if instruction.is_empty() {
-            instruction = "print Hello World".to_string();
+        if !self.print_only {
+            say!("Generating script...");
         }
 
-        println!("Generating script...");
-
-        let os = os_name();
-
+        let environment = self.environment.enabled.then(|| {
+            let interpreter = self.interpreters.resolve(&self.shell);
+            Environment::load_or_detect(&default_cache_path(), self.environment_cache_ttl, interpreter.program())
+        });
+        let os = match environment.as_ref().and_then(|e| e.distribution(&self.environment)) {
+            Some(distribution) => format!("{} ({})", os_name(), distribution),
+            None => os_name(),
+        };
 
-        let mut requirements = vec!["the script is meant to be run on a ".to_string() + &os + " machine"];
+        let mut blocks = vec![ContextBlock {
+            name: "os".to_string(),
+            content: "the script is meant to be run on a ".to_string() + &os + " machine",
+            priority: PRIORITY_BUILTIN,
+        }];
+        if let Some(environment) = environment {
+            let description = environment.describe(&self.environment);
+            if !description.is_empty() {
+                blocks.push(ContextBlock {
+                    name: "environment".to_string(),
+                    content: description,
+                    priority: PRIORITY_BUILTIN,
+                });
+            }
+        }
+        blocks.extend(extra_blocks);
+        blocks.extend(self.attachment_blocks());
+        blocks.extend(self.evaluate_context_calls(&context_calls));
 
+        // the output of --print-only goes into the prompt of an interactive shell, so it must not be a script
+        // whose shebang and strict mode would stay in effect there
+        let prompt_template = if self.print_only {
+            PromptTemplate::load_command()
+        } else {
+            PromptTemplate::load(&self.shell)
+        }.unwrap_or_else(|err| {
+            eprintln!("Failed to read system prompt template: {}", err);
+            std::process::exit(1);
+        });
+        self.print_verbose(&format!("System prompt template: {}", prompt_template.source));
 
-        for c in context_calls {
-            let result = read_and_evaluate_context_file_by_name(&c.name, &self.context_shell, &c.args_as_str_vec());
+        let estimator = TokenEstimator::for_model(&self.model);
+        let examples = self.select_examples(&instruction, &estimator);
 
-            let content = result.unwrap_or_else(|err| {
-                eprintln!("Failed to read context file {}: {}", c.name, err);
-                std::process::exit(1);
-            });
+        let base_prompt = create_chat_prompt(&ScriptSpecification {
+            lang: self.shell.clone(),
+            instruction: instruction.clone(),
+            requirements: vec![],
+            examples: examples.clone(),
+            history: history.to_vec(),
+            repairs: vec![],
+        }, &prompt_template).to_string();
+        let budget = self.context_token_budget(&base_prompt, &estimator);
+        let (blocks, usage) = apply_budget(blocks, budget, self.context_budget.strategy, &estimator);
 
-            requirements.push(content);
+        self.print_verbose(&format!("Context token budget: {} (token counts are estimated)", budget));
+        for u in &usage {
+            self.print_verbose(&format!("  {}", u.describe()));
         }
 
-        let spec = generator::ScriptSpecification {
+        let spec = ScriptSpecification {
             lang: self.shell.clone(),
             instruction,
-            requirements,
+            requirements: blocks.into_iter().map(|b| b.content).collect(),
+            examples,
+            history: history.to_vec(),
+            repairs: vec![],
         };
 
         let generator =
             generator::ChatGptScriptGenerator::new(
-                self.open_ai_api_key.clone(),
-                self.model.clone());
+                api_key,
+                self.model.clone(),
+                self.open_ai_base_url.clone(),
+                HttpClient::new(&self.http),
+                self.sampling.clone(),
+                self.candidates,
+                prompt_template.clone());
 
         self.print_verbose(&format!("Using generator: {:?}", generator));
         self.print_verbose("=== Prompt ===");
-        self.print_verbose_lazy(|| format!("{}", create_chat_prompt(&spec)));
+        self.print_verbose_lazy(|| format!("{}", create_chat_prompt(&spec, &prompt_template)));
+
 
+        let generation = generator.generate(&spec).map_err(|err| err.to_string())?;
 
-        let script = generator.generate(&spec);
+        let context_names = context_calls.iter().map(|c| c.name.clone()).collect::<Vec<String>>();
+        self.record_usage(generation.usage, &context_names);
 
-        let do_run_script = match self.run_mode {
-            config::RunMode::Ask => {
-                print_script(&script);
-                println!("Do you want to run this script? (y/n)");
-                let mut input = String::new();
-                io::stdin().read_line(&mut input).unwrap();
-                input.trim().to_lowercase() == "y"
+        let scripts = generation.scripts;
+        self.print_verbose(&format!("Unique candidates: {}", scripts.len()));
+
+        if self.print_only {
+            println!("{}", scripts[0]);
+            return Ok(Some(scripts[0].clone()));
+        }
+
+        let mut conversation = Conversation {
+            generator: &generator,
+            spec,
+            context_names,
+        };
+
+        let script = match self.run_mode {
+            config::RunMode::Ask if scripts.len() > 1 => pick_candidate(&scripts, &self.shell)
+                .and_then(|script| self.review(&mut conversation, script.clone(), true)),
+            config::RunMode::Ask | config::RunMode::Force => self.review(&mut conversation, scripts[0].clone(), false),
+            config::RunMode::Dry if raw_output() || self.output.is_some() => {
+                if scripts.len() > 1 {
+                    say!("{} candidates were generated, using the first one", scripts.len());
+                }
+                print_checks(&syntax_checks(&scripts[0], &self.shell));
+                self.print_or_save(&scripts[0]);
+                None
             }
-            config::RunMode::Force => true,
             config::RunMode::Dry => {
-                print_script(&script);
-                false
+                print_candidates(&scripts, &self.shell);
+                None
+            }
+        };
+
+        match script {
+            Some(script) if self.output.is_some() => {
+                self.print_or_save(&script);
+                Ok(Some(script))
+            }
+            Some(script) => Ok(Some(self.execute(&mut conversation, script))),
+            None => {
+                if self.run_mode == config::RunMode::Ask && self.session_cwd_file.is_none() {
+                    say!("Ok, see you later!");
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Saves the script to the `--output` file, or prints it to stdout without decoration.
+    fn print_or_save(&self, script: &str) {
+        let path = match &self.output {
+            Some(path) => path,
+            None => {
+                println!("{}", script);
+                return;
+            }
+        };
+        let suffix = &self.interpreters.find_by_shebang(script)
+            .cloned()
+            .unwrap_or_else(|| self.interpreters.resolve(&self.shell))
+            .suffix;
+        match save_script(path, script, suffix) {
+            Ok(path) => say!("Saved the script to {}", path.display()),
+            Err(err) => {
+                eprintln!("Failed to save the script to {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Shows the script with its syntax check and, in ask mode, asks whether to run it
+    /// (unless it was `picked` from the candidates already). A failed syntax check can be repaired once.
+    /// Returns the script to run.
+    fn review(&self, conversation: &mut Conversation, script: String, picked: bool) -> Option<String> {
+        let ask = self.run_mode == config::RunMode::Ask;
+        let checks = if ask && !picked {
+            print_script(&script, &self.shell)
+        } else {
+            let checks = syntax_checks(&script, &self.shell);
+            if !ask {
+                print_checks(&checks);
+            }
+            checks
+        };
+
+        let (script, confirmed) = match self.offer_syntax_repair(conversation, &script, &checks) {
+            Some(repaired) => {
+                if ask {
+                    print_script(&repaired, &self.shell);
+                }
+                (repaired, false)
+            }
+            None => (script, picked),
+        };
+        let question = match &self.output {
+            Some(path) => format!("Do you want to save this script to {}? (y/n)", path.display()),
+            None => "Do you want to run this script? (y/n)".to_string(),
+        };
+        if !ask || confirmed || ask_yes_no(&question) {
+            Some(script)
+        } else {
+            None
+        }
+    }
+
+    /// Runs the script. With `--fix`, a failing script is sent back to the model together with its
+    /// exit code and stderr, and the corrected script is reviewed and run again, up to `--fix-attempts` times.
+    /// In an interactive session, the working directory follows the script. Returns the last script that was run.
+    fn execute(&self, conversation: &mut Conversation, script: String) -> String {
+        let mut script = script;
+        let mut attempt = 0;
+        loop {
+            let interpreter = self.interpreter_for(&script);
+            self.print_verbose(&format!("Interpreter: {:?}", interpreter));
+            let script_to_run = match &self.session_cwd_file {
+                Some(cwd_file) => {
+                    // the file stays empty if the script cannot write its directory, e.g. when it is killed
+                    let _ = std::fs::write(cwd_file, "");
+                    with_cwd_tracking(&script, &interpreter, cwd_file)
+                }
+                None => script.clone(),
+            };
+            // stderr is only captured when --fix needs it
+            let mut runner = SimpleScriptRunner::with_interpreter(interpreter);
+            if self.fix {
+                runner = runner.capturing_stderr();
+            }
+            say!("Running script...");
+            let colored_output = !raw_output() && colored::control::SHOULD_COLORIZE.should_colorize();
+            if colored_output {
+                // start blue ansi color
+                print!("\x1b[34m");
+            }
+
+            let outcome = runner.run_script(&script_to_run, &[], None);
+            if colored_output {
+                // reset color
+                print!("\x1b[0m");
             }
+            if let Some(dir) = self.session_cwd_file.as_deref().and_then(read_cwd) {
+                if let Err(err) = std::env::set_current_dir(&dir) {
+                    eprintln!("Failed to change the working directory to {}: {}", dir.display(), err);
+                }
+            }
+
+            let outcome = match outcome {
+                Ok(outcome) => outcome,
+                Err(err) => {
+                    eprintln!("Failed to run script: {}", err);
+                    return script;
+                }
+            };
+            if outcome.success() || !self.fix {
+                return script;
+            }
+            if attempt >= self.fix_attempts {
+                say!("The script still fails after {} fix attempts, giving up.", attempt);
+                return script;
+            }
+            attempt += 1;
+
+            let exit_code = outcome.exit_code.map_or("none (killed by a signal)".to_string(), |c| c.to_string());
+            say!("{}", format!("The script failed with exit code {}. Fix attempt {}/{}",
+                                   exit_code, attempt, self.fix_attempts).yellow());
+            let problem = format!("It failed with exit code {}. Its stderr was:\n{}",
+                                  exit_code, tail(outcome.stderr.trim_end(), MAX_STDERR_CHARS));
+            script = match self.repair(conversation, &script, problem)
+                .and_then(|repaired| self.review(conversation, repaired, false)) {
+                Some(repaired) => repaired,
+                None => return script,
+            };
+        }
+    }
+
+    fn render_contexts(&self, context: &[String]) {
+        let mut context_calls = context.iter().map(|s| ContextCall::parse(s)).collect::<Vec<ContextCall>>();
+        if context_calls.is_empty() {
+            context_calls.push(ContextCall {
+                name: "default".to_string(),
+                args: vec![],
+            });
+        }
+
+        let estimator = TokenEstimator::for_model(&self.model);
+        let mut total = 0;
+        for block in self.evaluate_context_calls(&context_calls) {
+            let tokens = estimator.count(&block.content);
+            total += tokens;
+            println!("{}", format!("=== {} ({} tokens) ===", block.name, tokens).white());
+            println!("{}", block.content.trim_end());
+        }
+        println!("{}", format!("Total: {} tokens", total).white());
+    }
+}
+
+fn print_candidates(scripts: &[String], shell: &str) {
+    if scripts.len() == 1 {
+        print_script(&scripts[0], shell);
+        return;
+    }
+    for (i, script) in scripts.iter().enumerate() {
+        say!("{}", format!("Candidate {}/{}", i + 1, scripts.len()).white());
+        print_script(script, shell);
+    }
+}
+
+/// Shows all candidates and lets the user choose one, compare two of them, or reject all.
+fn pick_candidate<'a>(scripts: &'a [String], shell: &str) -> Option<&'a String> {
+    print_candidates(scripts, shell);
+    loop {
+        say!("Which script do you want to run? (1-{}, d <a> <b> to compare two candidates, n to reject all)",
+                 scripts.len());
+        let input = read_answer().trim().to_lowercase();
+
+        if input.is_empty() || input == "n" {
+            return None;
+        }
+
+        let parts = input.split_whitespace().collect::<Vec<&str>>();
+        let index = |s: &str| s.parse::<usize>().ok().filter(|i| (1..=scripts.len()).contains(i)).map(|i| i - 1);
+
+        match parts.as_slice() {
+            [n] => match index(n) {
+                Some(i) => return Some(&scripts[i]),
+                None => say!("There is no candidate {}", n),
+            },
+            ["d", a, b] => match (index(a), index(b)) {
+                (Some(a), Some(b)) => say!("{}", format_diff(&diff_lines(&scripts[a], &scripts[b]))),
+                _ => say!("Invalid candidates: {} {}", a, b),
+            },
+            _ => say!("Invalid input: {}", input),
+        }
+    }
+}
+
+/// The language of the script's shebang, or else the given shell.
+fn script_lang(script: &str, shell: &str) -> String {
+    parse_shebang(script).unwrap_or_else(|| shell.to_string())
+}
+
+/// Prints the script highlighted for its language, followed by the result of the syntax check.
+fn print_script(script: &str, shell: &str) -> Vec<SyntaxCheck> {
+    say!("{}", "=".repeat(80).white());
+    say!("{}", highlight(script, &Syntax::for_lang(&script_lang(script, shell))));
+    say!("{}", "=".repeat(80).white());
+    let checks = syntax_checks(script, shell);
+    print_checks(&checks);
+    checks
+}
+
+fn syntax_checks(script: &str, shell: &str) -> Vec<SyntaxCheck> {
+    check_syntax(script, &script_lang(script, shell)).unwrap_or_else(|err| {
+        eprintln!("Failed to check the script: {}", err);
+        vec![]
+    })
+}
+
+fn print_checks(checks: &[SyntaxCheck]) {
+    for check in checks {
+        if check.passed {
+            say!("{}", format!("Syntax check ({}): ok", check.tool).green());
+        } else {
+            say!("{}", format!("Syntax check ({}): failed", check.tool).red());
+            say!("{}", check.output);
+        }
+    }
+}
+
+/// The last `max_chars` characters of the text.
+fn tail(text: &str, max_chars: usize) -> &str {
+    let count = text.chars().count();
+    match text.char_indices().nth(count.saturating_sub(max_chars)) {
+        Some((start, _)) if count > max_chars => &text[start..],
+        _ => text,
+    }
+}
+
+/// The output of the failed checks, or `None` if all checks passed.
+fn syntax_problem(checks: &[SyntaxCheck]) -> Option<String> {
+    let failed = checks.iter()
+        .filter(|c| !c.passed)
+        .map(|c| format!("{} reports:\n{}", c.tool, c.output))
+        .collect::<Vec<String>>();
+    if failed.is_empty() { None } else { Some(failed.join("\n\n")) }
+}
+
+fn ask_yes_no(question: &str) -> bool {
+    say!("{}", question);
+    read_answer().trim().to_lowercase() == "y"
+}
+
+/// Reads a line typed by the user. If stdin is piped into houston, the line is read from the terminal instead.
+fn read_answer() -> String {
+    let mut input = String::new();
+    if !io::stdin().is_terminal() {
+        let terminal = if cfg!(windows) { "CONIN$" } else { "/dev/tty" };
+        if let Ok(tty) = std::fs::File::open(terminal) {
+            io::BufReader::new(tty).read_line(&mut input).unwrap();
+            return input;
+        }
+    }
+    io::stdin().read_line(&mut input).unwrap();
+    input
+}
+
+/// The prompt of the interactive session, with the working directory (`~` for the home directory).
+fn repl_prompt() -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let dir = match dirs::home_dir().and_then(|home| cwd.strip_prefix(home).ok().map(|p| p.to_path_buf())) {
+        Some(relative) if relative.as_os_str().is_empty() => "~".to_string(),
+        Some(relative) => format!("~/{}", relative.display()),
+        None => cwd.display().to_string(),
+    };
+    format!("hu {}> ", dir)
+}
+
+/// How long a pipe on stdin may stay silent before houston explains what it is waiting for, see [piped_stdin].
+const STDIN_HINT_DELAY: Duration = Duration::from_secs(1);
+
+/// Set to skip stdin, e.g. when houston inherits a pipe that is never closed from an editor or CI runner.
+const NO_STDIN_VAR: &str = "HU_NO_STDIN";
+
+/// The input piped into houston, if any. Slow producers like `make 2>&1 | hu why` are waited for, so a pipe that
+/// stays silent for a while only gets a hint on how to skip it with [NO_STDIN_VAR] or `</dev/null`.
+fn piped_stdin() -> Option<String> {
+    if io::stdin().is_terminal() || std::env::var_os(NO_STDIN_VAR).is_some_and(|v| !v.is_empty()) {
+        return None;
+    }
+    if stdin_is_file() {
+        return Some(read_stdin("stdin"));
+    }
+    let (sender, started) = mpsc::channel();
+    let reader = std::thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        // returns as soon as there is data or the pipe is closed
+        let _ = stdin.fill_buf();
+        let _ = sender.send(());
+        let mut input = String::new();
+        stdin.read_to_string(&mut input).map(|_| input)
+    });
+    if started.recv_timeout(STDIN_HINT_DELAY).is_err() {
+        eprintln!("{}", format!("Waiting for input on stdin. Set {}=1 or use </dev/null to skip it.", NO_STDIN_VAR).yellow());
+    }
+    match reader.join() {
+        Ok(Ok(input)) => Some(input),
+        Ok(Err(err)) => {
+            eprintln!("Failed to read stdin: {}", err);
+            std::process::exit(1);
+        }
+        Err(_) => None,
+    }
+}
+
+#[cfg(unix)]
+fn stdin_is_file() -> bool {
+    use std::os::fd::AsFd;
+    io::stdin().as_fd().try_clone_to_owned()
+        .map(std::fs::File::from)
+        .and_then(|file| file.metadata())
+        .is_ok_and(|metadata| metadata.is_file())
+}
+
+#[cfg(not(unix))]
+fn stdin_is_file() -> bool {
+    false
+}
+
+fn read_stdin(what: &str) -> String {
+    let mut input = String::new();
+    if let Err(err) = io::stdin().read_to_string(&mut input) {
+        eprintln!("Failed to read {} from stdin: {}", what, err);
+        std::process::exit(1);
+    }
+    input
+}
+
+fn print_usage(since: Option<&str>, by: Option<&str>) {
+    let since = match since.map(parse_duration) {
+        Some(Ok(seconds)) => houston::usage::now().saturating_sub(seconds),
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        None => 0,
+    };
+    let grouping = match by {
+        Some("model") => Some(UsageGrouping::Model),
+        Some("context") => Some(UsageGrouping::Context),
+        _ => None,
+    };
+
+    let records = UsageLedger::default_location().read().unwrap_or_else(|err| {
+        eprintln!("Failed to read usage ledger: {}", err);
+        std::process::exit(1);
+    });
+    let summary = summarize(&records, since, grouping);
+
+    if summary.is_empty() {
+        println!("No usage recorded.");
+        return;
+    }
+
+    let header = by.unwrap_or("");
+    println!("{}", format!("{:<24} {:>8} {:>12} {:>12} {:>10}", header, "calls", "prompt", "completion", "cost").white());
+    for (name, s) in &summary {
+        let unpriced = if s.unpriced_invocations > 0 {
+            format!(" ({} without price)", s.unpriced_invocations)
+        } else {
+            String::new()
         };
+        println!("{:<24} {:>8} {:>12} {:>12} {:>10}{}", name, s.invocations, s.usage.prompt_tokens,
+                 s.usage.completion_tokens, format!("${:.4}", s.cost), unpriced);
+    }
+}
+
+fn config_command(command: &ConfigCommand, profile: Option<&str>) {
+    match command {
+        ConfigCommand::Show { origin } => show_config(*origin, profile),
+        ConfigCommand::Get { key } => get_config(key, profile),
+        ConfigCommand::Set { key, value, project } => {
+            let path = config_target(*project);
+            let mut document = read_config_document(&path);
+            let key_path = resolve_key(&document, key);
+            config_file::set(&mut document, &key_path, parse_scalar(value));
+            write_config_document(&path, &document);
+        }
+        ConfigCommand::Unset { key, project } => {
+            let path = config_target(*project);
+            let mut document = read_config_document(&path);
+            let key_path = resolve_key(&document, key);
+            if !config_file::unset(&mut document, &key_path) {
+                println!("{} is not set in {}", key, path.display());
+                return;
+            }
+            write_config_document(&path, &document);
+        }
+        ConfigCommand::Edit { project } => edit_config(&config_target(*project)),
+        ConfigCommand::Validate => validate_config(),
+        ConfigCommand::Profiles => list_profiles(profile),
+        ConfigCommand::Migrate { dry_run, project } => migrate_config(&config_target(*project), *dry_run),
+    }
+}
+
+/// The path of a dotted key, split where the document has keys with dots (e.g. `pricing.gpt-4.1`),
+/// or else by the keys the config understands. Exits if the config does not have the key.
+fn resolve_key(document: &serde_yaml::Value, key: &str) -> Vec<String> {
+    let path = config_file::key_path(document, key);
+    match config_file::check_key(&path) {
+        None => path,
+        Some(problem) => config_file::schema_key_path(key).unwrap_or_else(|| {
+            eprintln!("{}", problem);
+            std::process::exit(1);
+        }),
+    }
+}
+
+fn load_merged_config_or_exit(profile: Option<&str>) -> MergedConfig {
+    let mut merged = config::load_merged_config(profile).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    obfuscate_api_keys(&mut merged.value);
+    merged
+}
+
+/// Obfuscates `apiKey` in `openAi` and in the profiles.
+fn obfuscate_api_keys(value: &mut serde_yaml::Value) {
+    if let serde_yaml::Value::Mapping(mapping) = value {
+        for (key, value) in mapping.iter_mut() {
+            match value {
+                serde_yaml::Value::String(api_key) if key.as_str() == Some("apiKey") => *api_key = obfuscate_api_key(api_key),
+                value => obfuscate_api_keys(value),
+            }
+        }
+    }
+}
+
+fn get_config(key: &str, profile: Option<&str>) {
+    let merged = load_merged_config_or_exit(profile);
+    let path = resolve_key(&merged.value, key);
+    match path.iter().try_fold(&merged.value, |v, k| v.get(k.as_str())) {
+        Some(value @ serde_yaml::Value::Mapping(_)) => print!("{}", serde_yaml::to_string(value).unwrap()),
+        Some(value) => println!("{}", format_value(value)),
+        None => println!("~"),
+    }
+}
+
+/// The user config, or with `--project` the config of the nearest project (created in the working directory if there is none).
+fn config_target(project: bool) -> PathBuf {
+    if project {
+        config::project_config_path().unwrap_or_else(|| PathBuf::from(".houston").join("config.yml"))
+    } else {
+        config::user_config_path()
+    }
+}
+
+fn read_config_document(path: &Path) -> serde_yaml::Value {
+    if !path.exists() {
+        let mut document = serde_yaml::Mapping::new();
+        document.insert("version".into(), CONFIG_VERSION.into());
+        return serde_yaml::Value::Mapping(document);
+    }
+    let text = std::fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Failed to read {}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+    match serde_yaml::from_str(&text) {
+        Ok(serde_yaml::Value::Null) => serde_yaml::Value::Mapping(Default::default()),
+        Ok(document) => migrate_document(path, document),
+        Err(err) => {
+            eprintln!("Failed to parse {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Upgrades the document of an older config file that is about to be rewritten, after copying the file
+/// to its backup path.
+fn migrate_document(path: &Path, mut document: serde_yaml::Value) -> serde_yaml::Value {
+    let from_version = version_of(&document).unwrap_or_else(|err| {
+        eprintln!("Invalid config {}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+    let serde_yaml::Value::Mapping(_) = &document else {
+        return document;
+    };
+    if from_version == CONFIG_VERSION {
+        return document;
+    }
+    let applied = migrate_value(&mut document, &migrations()).unwrap_or_else(|err| {
+        eprintln!("Failed to migrate {}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+    print_migrations(path, from_version, &applied);
+    match backup(path, from_version) {
+        Ok(backup) => eprintln!("  The original is saved as {}.", backup.display()),
+        Err(err) => {
+            eprintln!("Failed to save a backup of {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+    // the version goes first, like in new configs
+    let mut ordered = serde_yaml::Mapping::new();
+    ordered.insert("version".into(), CONFIG_VERSION.into());
+    if let serde_yaml::Value::Mapping(mapping) = document {
+        ordered.extend(mapping.into_iter().filter(|(key, _)| key.as_str() != Some("version")));
+    }
+    serde_yaml::Value::Mapping(ordered)
+}
 
-        if do_run_script {
-            let runner = SimpleScriptRunner::new(&self.shell);
-            print!("Running script...");
-            // start blue ansi color
-            println!("\x1b[34m");
+/// Checks the text of a config file, with the restrictions of a project config if it is one.
+fn validate_config_text(path: &Path, text: &str) -> Vec<ConfigProblem> {
+    if config::is_project_config(path) {
+        config_file::validate_project(text)
+    } else {
+        config_file::validate(text)
+    }
+}
+
+/// Writes the document if it is a valid config. The file is changed line by line to keep its comments,
+/// or else rewritten, after a warning if it has comments.
+fn write_config_document(path: &Path, document: &serde_yaml::Value) {
+    let original = std::fs::read_to_string(path).unwrap_or_default();
+    let text = config_file::edit_text(&original, document).unwrap_or_else(|| {
+        if config_file::has_comments(&original) {
+            eprintln!("{}", format!("Warning: {} cannot be changed line by line, so its comments are not kept.",
+                                    path.display()).yellow());
+        }
+        serde_yaml::to_string(document).unwrap()
+    });
+    let problems = validate_config_text(path, &text);
+    if !problems.is_empty() {
+        eprintln!("{}", format_problems(&path.display().to_string(), &problems));
+        eprintln!("The change was not saved.");
+        std::process::exit(1);
+    }
+    let result = path.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|_| std::fs::write(path, text));
+    if let Err(err) = result {
+        eprintln!("Failed to write {}: {}", path.display(), err);
+        std::process::exit(1);
+    }
+}
+
+/// `$VISUAL` or `$EDITOR`, which may contain arguments (e.g. `code --wait`).
+fn editor_command() -> Vec<String> {
+    let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    editor.split_whitespace().map(|s| s.to_string()).collect()
+}
+
+fn edit_config(path: &Path) {
+    if !path.exists() {
+        write_config_document(path, &read_config_document(path));
+    }
+    let editor = editor_command();
+    loop {
+        let status = std::process::Command::new(&editor[0]).args(&editor[1..]).arg(path).status();
+        if let Err(err) = status {
+            eprintln!("Failed to start the editor {}: {}", editor[0], err);
+            std::process::exit(1);
+        }
+
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        let problems = validate_config_text(path, &text);
+        if problems.is_empty() {
+            println!("{} is valid.", path.display());
+            return;
+        }
+        eprintln!("{}", format_problems(&path.display().to_string(), &problems).red());
+        if !ask_yes_no("Do you want to edit the file again? (y/n)") {
+            std::process::exit(1);
+        }
+    }
+}
+
+fn validate_config() {
+    let files = config::config_file_paths();
+
+    let mut valid = true;
+    for path in files.iter().filter(|p| p.exists()) {
+        let problems = std::fs::read_to_string(path)
+            .map(|text| validate_config_text(path, &text))
+            .unwrap_or_else(|err| vec![ConfigProblem { line: None, message: err.to_string() }]);
+        if problems.is_empty() {
+            println!("{}: {}", path.display(), "ok".green());
+        } else {
+            valid = false;
+            println!("{}", format_problems(&path.display().to_string(), &problems).red());
+        }
+    }
+    for layer in env_layers(std::env::vars()) {
+        let problems = config_file::validate_value(&layer.value);
+        if !problems.is_empty() {
+            valid = false;
+            println!("{}", format_problems(&layer.origin.to_string(), &problems).red());
+        }
+    }
+    if !valid {
+        std::process::exit(1);
+    }
+}
+
+fn show_config(origin: bool, profile: Option<&str>) {
+    let merged = load_merged_config_or_exit(profile);
+    if !origin {
+        print!("{}", serde_yaml::to_string(&merged.value).unwrap());
+        return;
+    }
+    for (path, value, origin) in merged.entries() {
+        let origin = origin.map(|o| o.to_string()).unwrap_or_default();
+        println!("{} = {}  {}", path.join("."), format_value(value), format!("({})", origin).bright_black());
+    }
+}
+
+/// Asks a question with a default answer, which is used if the user just presses enter.
+fn ask(question: &str, default: &str) -> String {
+    if default.is_empty() {
+        say!("{}", question);
+    } else {
+        say!("{} [{}]", question, default);
+    }
+    let answer = read_answer().trim().to_string();
+    if answer.is_empty() { default.to_string() } else { answer }
+}
+
+/// Lets the user pick one of the options by number. Returns its index.
+fn choose(question: &str, options: &[String], default: usize) -> usize {
+    say!("{}", question);
+    for (i, option) in options.iter().enumerate() {
+        say!("  {}) {}", i + 1, option);
+    }
+    loop {
+        let answer = ask("Your choice:", &(default + 1).to_string());
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=options.len()).contains(&n) => return n - 1,
+            _ => say!("Please enter a number from 1 to {}.", options.len()),
+        }
+    }
+}
+
+fn read_secret(prompt: &str) -> String {
+    loop {
+        match rpassword::prompt_password(prompt) {
+            Ok(secret) if !secret.trim().is_empty() => return secret.trim().to_string(),
+            Ok(_) => say!("The key must not be empty."),
+            Err(err) => {
+                eprintln!("Failed to read the key: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn choose_shell(question: &str, shells: &[DetectedShell], default: &str) -> String {
+    let options = shells.iter()
+        .map(|s| format!("{}{}", s.name, s.version.as_ref().map(|v| format!(" ({})", v)).unwrap_or_default()))
+        .collect::<Vec<String>>();
+    let default = shells.iter().position(|s| s.name == default).unwrap_or(0);
+    shells[choose(question, &options, default)].name.clone()
+}
+
+/// `hu setup`: asks for the settings a new user needs and writes them to the user config.
+fn setup() {
+    say!("{}", "Houston setup".bold());
+    say!("Your answers are saved in {}. Press enter to use the suggestion in brackets.", config::user_config_path().display());
+    say!("");
+
+    let shells = available_shells();
+    let (shell, context_shell) = if shells.is_empty() {
+        let shell = ask("Which shell should run the generated scripts?", if cfg!(windows) { "powershell" } else { "bash" });
+        (shell.clone(), shell)
+    } else {
+        let login_shell = std::env::var("SHELL").ok()
+            .and_then(|s| Path::new(&s).file_name().map(|n| n.to_string_lossy().to_string()))
+            .unwrap_or_default();
+        let shell = choose_shell("Which shell should run the generated scripts?", &shells, &login_shell);
+        let context_shell = choose_shell("Which shell should evaluate the ${...} commands in context files?", &shells, &shell);
+        (shell, context_shell)
+    };
+    say!("");
+
+    let endpoints = vec![
+        "OpenAI".to_string(),
+        "Another OpenAI compatible API (e.g. a proxy, Ollama or LM Studio)".to_string(),
+    ];
+    let (base_url, default_model) = match choose("Which API do you want to use?", &endpoints, 0) {
+        0 => (DEFAULT_OPEN_AI_BASE_URL.to_string(), "gpt-4o"),
+        _ => (ask("The base URL of the API (the part before /chat/completions):", "http://localhost:11434/v1"), ""),
+    };
+    let model = loop {
+        let model = ask("Which model do you want to use?", default_model);
+        if !model.is_empty() {
+            break model;
+        }
+    };
+    say!("");
+
+    let env_key = std::env::var("OPENAI_API_KEY").ok().filter(|k| !k.is_empty());
+    let key_file = config::get_houston_dir().join("openai.key");
+    let sources = vec![
+        format!("The OPENAI_API_KEY environment variable{}", if env_key.is_some() { " (it is set)" } else { "" }),
+        format!("A file only you can read ({})", key_file.display()),
+        "A command that prints the key, e.g. from a password manager (pass, op, ...)".to_string(),
+        "The config file".to_string(),
+    ];
+    let default_source = if env_key.is_some() { 0 } else { 1 };
+    let (key_source, api_key) = match choose("Where do you want to keep the API key?", &sources, default_source) {
+        0 => {
+            if env_key.is_none() {
+                say!("Remember to set OPENAI_API_KEY in your shell profile.");
+            }
+            (KeySource::Env, env_key)
+        }
+        1 => {
+            let api_key = read_secret("API key (the input is hidden): ");
+            if let Err(err) = write_api_key_file(&key_file, &api_key) {
+                eprintln!("Failed to write {}: {}", key_file.display(), err);
+                std::process::exit(1);
+            }
+            (KeySource::File(key_file), Some(api_key))
+        }
+        2 => {
+            let command = loop {
+                let command = ask("The command (e.g. `pass show openai` or `op read op://Private/OpenAI/credential`):", "");
+                if !command.is_empty() {
+                    break command;
+                }
+            };
+            let api_key = run_api_key_command(&command).map_err(|err| say!("{}", err.yellow())).ok();
+            (KeySource::Command(command), api_key)
+        }
+        _ => {
+            let api_key = read_secret("API key (the input is hidden): ");
+            (KeySource::Config(api_key.clone()), Some(api_key))
+        }
+    };
+    say!("");
+
+    match &api_key {
+        Some(api_key) if ask_yes_no(&format!("Check the connection to {}? (y/n)", base_url)) => {
+            match check_connection(&base_url, api_key) {
+                Ok(models) if models.is_empty() || models.contains(&model) => say!("{}", "The connection works.".green()),
+                Ok(models) => {
+                    say!("{}", format!("The connection works, but the API does not list the model {}.", model).yellow());
+                    say!("Some of the available models: {}", models.iter().take(10).cloned().collect::<Vec<String>>().join(", "));
+                }
+                Err(err) => say!("{}", format!("The connection failed: {}", ApiKey(api_key.clone()).hide_in(&err.to_string())).red()),
+            }
+        }
+        _ => {}
+    }
+    say!("");
+
+    let answers = SetupAnswers { shell, context_shell, base_url, model, key_source };
+    let path = config::user_config_path();
+    let mut document = read_config_document(&path);
+    // only the chosen key source is kept, since apiKey would win over the others
+    for key in ["openAi.apiKey", "openAi.apiKeyFile", "openAi.apiKeyCommand", "openAi.baseUrl"] {
+        config_file::unset(&mut document, &key.split('.').map(|k| k.to_string()).collect::<Vec<String>>());
+    }
+    for (key, value) in config_entries(&answers) {
+        config_file::set(&mut document, &key, value);
+    }
+    // the key must not be readable by others for a moment, so the file is made private before it is written
+    if matches!(answers.key_source, KeySource::Config(_)) {
+        if let Err(err) = create_private_file(&path) {
+            eprintln!("Failed to make {} private: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+    write_config_document(&path, &document);
+    say!("Saved {}.", path.display());
+
+    if does_default_ctxt_exist() {
+        say!("You already have a default.ctxt, it is left as it is.");
+        return;
+    }
+    say!("");
+    say!("The default context file tells Houston about your preferences for every script.");
+    let preferences = PREFERENCES.iter().filter(|p| ask_yes_no(&format!("{} (y/n)", p.question))).collect::<Vec<&Preference>>();
+    let context_path = config::get_houston_dir().join("default.ctxt");
+    match std::fs::write(&context_path, default_context(&preferences)) {
+        Ok(()) => say!("Saved {}. You can add more to it at any time.", context_path.display()),
+        Err(err) => say!("{}", format!("Failed to write {}: {}", context_path.display(), err).red()),
+    }
+}
+
+fn migrate_config(path: &Path, dry_run: bool) {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        println!("{} does not exist, there is nothing to migrate.", path.display());
+        return;
+    };
+    let migrated = migrate_text(&text, &migrations()).unwrap_or_else(|err| {
+        eprintln!("Failed to migrate {}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+    let Some(migrated) = migrated else {
+        println!("{} is up to date (version {}).", path.display(), CONFIG_VERSION);
+        return;
+    };
+
+    println!("{}", format_diff(&diff_lines(&text, &migrated.text)));
+    print_migrations(path, migrated.from_version, &migrated.applied);
+    if dry_run {
+        println!("Nothing was written (--dry-run).");
+        return;
+    }
+    match write_migrated(path, &migrated) {
+        Ok(backup) => println!("The original is saved as {}.", backup.display()),
+        Err(err) => {
+            eprintln!("Failed to migrate {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
 
-            let _ = runner.run_script(&script, &[], None);
-            // reset color
-            print!("\x1b[0m");
-        } else if self.run_mode == config::RunMode::Ask {
-            println!("Ok, see you later!");
+fn print_migrations(path: &Path, from_version: u32, applied: &[&str]) {
+    eprintln!("Migrating {} from config version {} to {}:", path.display(), from_version, CONFIG_VERSION);
+    for description in applied {
+        eprintln!("  - {}", description);
+    }
+}
+
+fn list_profiles(profile: Option<&str>) {
+    let merged = load_merged_config_or_exit(None);
+    let profiles = profiles(&merged.value).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    if profiles.is_empty() {
+        println!("No profiles are configured.");
+        return;
+    }
+    let env = std::env::var(PROFILE_ENV_VAR).ok();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let selected = select_profile(&profiles, profile, env.as_deref(), &cwd, dirs::home_dir().as_deref())
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+
+    for (name, profile) in &profiles {
+        match &selected {
+            Some((selected, selected_by)) if selected == name => {
+                println!("{} {}", format!("* {}", name).green().bold(), format!("(selected by {})", selected_by).bright_black());
+            }
+            _ => println!("  {}", name),
+        }
+        if !profile.directories.is_empty() {
+            println!("    directories: {}", profile.directories.join(", "));
         }
+        let keys = leaf_paths(&serde_yaml::Value::Mapping(profile.overrides.clone())).into_iter()
+            .map(|path| path.join("."))
+            .collect::<Vec<String>>();
+        println!("    overrides: {}", if profile.overrides.is_empty() { "nothing".to_string() } else { keys.join(", ") });
     }
 }
 
-fn print_script(script: &str) {
-    println!("{}", "=".repeat(80).white());
-    println!("{}", script.green());
-    println!("{}", "=".repeat(80).white());
+/// Parses the command line. An instruction may start with the name of a subcommand, e.g. `hu init a git repo`:
+/// if the words after the name are not valid for the subcommand, they are all taken as the instruction.
+/// `hu -- <instruction>` always takes the words as an instruction.
+fn parse_options() -> CliOptions {
+    let args = std::env::args().collect::<Vec<String>>();
+    let mut options = match CliOptions::try_parse_from(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            let instruction = matches!(err.kind(),
+                ErrorKind::UnknownArgument | ErrorKind::InvalidSubcommand | ErrorKind::InvalidValue)
+                .then(|| instruction_args(&args))
+                .flatten()
+                .and_then(|args| CliOptions::try_parse_from(args).ok());
+            instruction.unwrap_or_else(|| err.exit())
+        }
+    };
+    // `hu fix the permissions of ~/.ssh` is an instruction, `hu fix git psuh` fixes a command
+    if let Some(Command::Fix { command, paste: false }) = &options.command {
+        let forced = args.windows(2).any(|w| w[0] == "fix" && w[1] == "--");
+        let program = command.first().map(|c| c.as_str()).unwrap_or_default();
+        if !command.is_empty() && !forced && !program.contains(['/', '\\']) && find_on_path(program).is_none() {
+            let mut instruction = vec!["fix".to_string()];
+            instruction.extend(command.iter().cloned());
+            options.instruction = instruction;
+            options.command = None;
+        }
+    }
+    options
+}
+
+/// The arguments with `--` before the first subcommand name, if it is followed by a word that is not an option.
+fn instruction_args(args: &[String]) -> Option<Vec<String>> {
+    let cli = CliOptions::command();
+    let subcommands = cli.get_subcommands().map(|c| c.get_name()).collect::<Vec<&str>>();
+    let position = args.iter().skip(1).position(|arg| subcommands.contains(&arg.as_str()))? + 1;
+    if args.get(position + 1).is_none_or(|next| next.starts_with('-')) {
+        return None;
+    }
+    let mut instruction_args = args.to_vec();
+    instruction_args.insert(position, "--".to_string());
+    Some(instruction_args)
 }
 
 fn main() {
-    let options = CliOptions::parse();
+    let mut options = parse_options();
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    let offer_setup = matches!(options.command,
+        None | Some(Command::Fix { .. }) | Some(Command::Ctx { .. }) | Some(Command::Shell));
+    if matches!(options.command, Some(Command::Setup)) {
+        setup();
+        return;
+    } else if offer_setup && interactive && !config::user_config_path().exists()
+        && ask_yes_no("Welcome to Houston! Do you want to set it up now? (y/n)") {
+        setup();
+    }
+    // older config files are migrated in memory when they are read, and on disk when they are changed
     config::create_user_config_if_not_exists();
-    let application = Application::from(options, config::load_user_config_strict().unwrap());
-    application.run();
+    // `hu config validate` reports them as errors
+    if !matches!(options.command, Some(Command::Config { command: ConfigCommand::Validate })) {
+        for warning in config::env_var_warnings() {
+            eprintln!("{}", format!("Warning: {}", warning).yellow());
+        }
+    }
+    match &options.command {
+        Some(Command::Usage { since, by }) => {
+            print_usage(since.as_deref(), by.as_deref());
+            return;
+        }
+        Some(Command::Init { shell }) => {
+            print!("{}", integration_script(shell).expect("clap only accepts supported shells"));
+            return;
+        }
+        Some(Command::Config { command }) => {
+            config_command(command, options.profile.as_deref());
+            return;
+        }
+        _ => {}
+    }
+
+    let generates_script = matches!(options.command, None | Some(Command::Fix { .. }));
+    if generates_script && (options.raw || options.print_only || !io::stdout().is_terminal()) {
+        RAW_OUTPUT.store(true, Ordering::Relaxed);
+        // everything but the script goes to stderr, so that decides about colors
+        if std::env::var_os("NO_COLOR").is_none() {
+            colored::control::set_override(io::stderr().is_terminal());
+        }
+    }
+
+    let command = options.command.take();
+    let mut application = config::load_user_config_strict(options.profile.as_deref())
+        .and_then(|user_config| Application::from(options, user_config))
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+    for warning in config::api_key_permission_warnings() {
+        eprintln!("{}", format!("Warning: {}", warning).yellow());
+    }
+    match command {
+        Some(Command::Ctx { command: CtxCommand::Render { context } }) => application.render_contexts(&context),
+        Some(Command::Fix { command, paste }) => application.fix(&command, paste),
+        Some(Command::Shell) => application.repl(),
+        Some(Command::Usage { .. }) | Some(Command::Init { .. }) | Some(Command::Config { .. }) | Some(Command::Setup) => unreachable!(),
+        None => application.run(),
+    }
 }
@@ -0,0 +1,121 @@
+use std::io;
+use std::process::Command;
+
+use crate::prompt::{canonical_lang, lang_file_stem};
+
+/// Placeholder for the path of the script file, for checkers that don't take it as the last argument.
+const SCRIPT_PLACEHOLDER: &str = "{script}";
+
+/// Reports the errors of a PowerShell script without running it.
+const POWERSHELL_PARSE: &str = "$errors = $null; \
+[System.Management.Automation.Language.Parser]::ParseFile('{script}', [ref]$null, [ref]$errors) | Out-Null; \
+$errors | ForEach-Object { \"line $($_.Extent.StartLineNumber): $($_.Message)\" }; \
+if ($errors) { exit 1 }";
+
+/// The result of one syntax checker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxCheck {
+    /// The checker, e.g. `bash -n`.
+    pub tool: String,
+    pub passed: bool,
+    /// What the checker reported, usually empty if the check passed.
+    pub output: String,
+}
+
+/// Checks a generated script without running it, using the checkers for the given language
+/// (e.g. `bash -n`, `python -m py_compile` and `shellcheck`).
+/// Checkers that are not installed are skipped, so the result may be empty.
+pub fn check_syntax(script: &str, lang: &str) -> io::Result<Vec<SyntaxCheck>> {
+    let dir = tempfile::tempdir()?;
+    let (suffix, checkers) = checkers(lang);
+    let path = dir.path().join(format!("script{}", suffix));
+    std::fs::write(&path, script)?;
+    let path = path.to_string_lossy();
+
+    let mut checks = Vec::new();
+    for (tool, command) in checkers {
+        let output = match command_for(&command, &path).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+
+        checks.push(SyntaxCheck {
+            tool: tool.to_string(),
+            passed: output.status.success(),
+            // the temp file name means nothing to the user or the model
+            output: text.replace(path.as_ref(), "script").trim().to_string(),
+        });
+    }
+    Ok(checks)
+}
+
+/// File suffix and (name, command line) of the checkers for a language.
+/// The path of the script is appended to the command line unless it contains `{script}`.
+fn checkers(lang: &str) -> (&'static str, Vec<(&'static str, Vec<&'static str>)>) {
+    let python = if cfg!(windows) { "python" } else { "python3" };
+    let lang = lang_file_stem(lang);
+    match canonical_lang(&lang) {
+        "bash" => (".sh", vec![("bash -n", vec!["bash", "-n"]), shellcheck("bash")]),
+        "sh" => (".sh", vec![("sh -n", vec!["sh", "-n"]), shellcheck("sh")]),
+        "zsh" => (".zsh", vec![("zsh -n", vec!["zsh", "-n"])]),
+        "fish" => (".fish", vec![("fish --no-execute", vec!["fish", "--no-execute"])]),
+        "python" => (".py", vec![("python -m py_compile", vec![python, "-m", "py_compile"])]),
+        "powershell" => {
+            let program = if lang == "pwsh" { "pwsh" } else { "powershell" };
+            (".ps1", vec![("PowerShell parser", vec![program, "-NoProfile", "-Command", POWERSHELL_PARSE])])
+        }
+        "node" => (".js", vec![("node --check", vec!["node", "--check"])]),
+        "ruby" => (".rb", vec![("ruby -c", vec!["ruby", "-c"])]),
+        "perl" => (".pl", vec![("perl -c", vec!["perl", "-c"])]),
+        "php" => (".php", vec![("php -l", vec!["php", "-l"])]),
+        _ => ("", vec![]),
+    }
+}
+
+fn command_for(command: &[&str], path: &str) -> Command {
+    let mut result = Command::new(command[0]);
+    if command.iter().any(|part| part.contains(SCRIPT_PLACEHOLDER)) {
+        // the placeholder is only used within single-quoted PowerShell strings
+        let quoted = path.replace('\'', "''");
+        result.args(command[1..].iter().map(|part| part.replace(SCRIPT_PLACEHOLDER, &quoted)));
+    } else {
+        result.args(&command[1..]).arg(path);
+    }
+    result
+}
+
+/// Only errors fail the check; warnings (e.g. unquoted variables) are common in working scripts.
+fn shellcheck(dialect: &'static str) -> (&'static str, Vec<&'static str>) {
+    ("shellcheck", vec!["shellcheck", "--shell", dialect, "--severity", "error", "--format", "gcc"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sh_syntax_error_is_reported() {
+        let checks = check_syntax("echo \"unbalanced\n", "sh").unwrap();
+
+        let sh = checks.iter().find(|c| c.tool == "sh -n").unwrap();
+        assert!(!sh.passed);
+        assert!(sh.output.contains("script"));
+        assert!(!sh.output.contains(".sh:"), "temp file path should be hidden: {}", sh.output);
+    }
+
+    #[test]
+    fn test_valid_sh_script_passes() {
+        let checks = check_syntax("#!/bin/sh\necho \"hello\"\n", "/bin/sh").unwrap();
+
+        assert!(checks.iter().any(|c| c.tool == "sh -n"));
+        assert!(checks.iter().all(|c| c.passed), "{:?}", checks);
+    }
+
+    #[test]
+    fn test_unknown_language_has_no_checkers() {
+        assert_eq!(check_syntax("whatever", "brainfuck").unwrap(), vec![]);
+    }
+}
@@ -1,11 +1,22 @@
+use std::collections::BTreeMap;
 use std::fmt;
-use std::fs::File;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+use crate::api_key::{contains_api_key, read_api_key_file, readable_by_others, run_api_key_command};
+use crate::interpreter::Interpreter;
+use crate::migration::CONFIG_VERSION;
+use crate::config_file::{unknown_keys, validate_value};
+use crate::layers::{ConfigLayer, ConfigOrigin, env_layers, format_problems, merge, MergedConfig, system_config_path};
+use crate::profile::profile_layer;
+use crate::tokens::TruncationStrategy;
+use crate::usage::{default_price_table, ModelPrice};
 
 
 const DEFAULT_CHAT_MODEL: &str = "gpt-4";
+pub const DEFAULT_OPEN_AI_BASE_URL: &str = "https://api.openai.com/v1/";
 const CONFIG_DIR_NAME: &str = "houston";
 const CONFIG_FILE_NAME: &str = "config.yml";
+const PROJECT_DIR_NAME: &str = ".houston";
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -15,6 +26,19 @@ pub enum RunMode {
     Dry,
 }
 
+/// What to do when the shebang of a generated script asks for a different interpreter than `--shell`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ShebangPolicy {
+    /// Ask whether to use the interpreter from the shebang (run with the requested shell if asking is not possible).
+    #[default]
+    Ask,
+    /// Always run the script with the interpreter from the shebang.
+    Always,
+    /// Always run the script with the requested shell.
+    Never,
+}
+
 #[derive(Clone)]
 pub struct ApiKey(pub String);
 
@@ -35,14 +59,28 @@ impl<'de> Deserialize<'de> for ApiKey {
     }
 }
 
-impl fmt::Debug for ApiKey {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fn obfuscate_api_key(api_key: &str) -> String {
-            let mut secret_api_key = api_key.to_string();
-            secret_api_key.replace_range(6.., "****");
-            secret_api_key
+/// Shortens an API key to its first characters, so that it can be shown.
+/// Short keys (which are not real keys, but might be passwords) are hidden completely.
+pub fn obfuscate_api_key(api_key: &str) -> String {
+    const SHOWN_CHARS: usize = 6;
+    if api_key.chars().count() < SHOWN_CHARS * 3 {
+        return "****".to_string();
+    }
+    format!("{}****", api_key.chars().take(SHOWN_CHARS).collect::<String>())
+}
+
+impl ApiKey {
+    /// Replaces the key in a text (e.g. an error message from the API) with its obfuscated form.
+    pub fn hide_in(&self, text: &str) -> String {
+        if self.0.is_empty() {
+            return text.to_string();
         }
+        text.replace(&self.0, &obfuscate_api_key(&self.0))
+    }
+}
 
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let short_api_key = obfuscate_api_key(&self.0);
         f.debug_tuple("ApiKey")
             .field(&short_api_key)
@@ -55,23 +93,297 @@ impl fmt::Debug for ApiKey {
 #[serde(rename_all = "camelCase", default)]
 pub struct OpenAiConfig {
     api_key: Option<ApiKey>,
+    /// A file that contains the key, only accessible by the user.
+    api_key_file: Option<String>,
+    /// A command that prints the key, e.g. `pass show openai`.
+    api_key_command: Option<String>,
     pub(crate) model: String,
+    pub(crate) base_url: String,
+    #[serde(flatten)]
+    pub(crate) sampling: SamplingParameters,
 }
 
 impl Default for OpenAiConfig {
     fn default() -> Self {
         OpenAiConfig {
             api_key: None,
+            api_key_file: None,
+            api_key_command: None,
             model: DEFAULT_CHAT_MODEL.to_string(),
+            base_url: DEFAULT_OPEN_AI_BASE_URL.to_string(),
+            sampling: default_sampling_parameters(),
+        }
+    }
+}
+
+impl OpenAiConfig {
+    fn api_key_source(&self) -> ApiKeySource {
+        ApiKeySource {
+            api_key: self.api_key.clone(),
+            api_key_file: self.api_key_file.clone(),
+            api_key_command: self.api_key_command.clone(),
+        }
+    }
+}
+
+/// Where the API key comes from. The key is only resolved when the API is called, so that commands
+/// that don't call it neither need a key nor run `apiKeyCommand`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKeySource {
+    api_key: Option<ApiKey>,
+    api_key_file: Option<String>,
+    api_key_command: Option<String>,
+}
+
+impl ApiKeySource {
+    /// The key from the first source that is set: `apiKey`, `apiKeyFile`, `apiKeyCommand`, `OPENAI_API_KEY`.
+    pub fn resolve(&self) -> Result<ApiKey, String> {
+        if let Some(api_key) = &self.api_key {
+            return Ok(api_key.clone());
         }
+        if let Some(path) = &self.api_key_file {
+            return read_api_key_file(Path::new(&expand_home(path, dirs::home_dir().as_deref()))).map(ApiKey);
+        }
+        if let Some(command) = &self.api_key_command {
+            return run_api_key_command(command).map(ApiKey);
+        }
+        std::env::var("OPENAI_API_KEY").ok().filter(|key| !key.is_empty()).map(ApiKey).ok_or_else(|| {
+            "OPENAI_API_KEY is not set. Run `hu setup`, or set openAi.apiKeyFile or openAi.apiKeyCommand in the config.".to_string()
+        })
     }
 }
 
+fn default_sampling_parameters() -> SamplingParameters {
+    SamplingParameters {
+        temperature: Some(0.5),
+        top_p: Some(1.0),
+        ..SamplingParameters::default()
+    }
+}
+
+/// Parameters that control how the model samples its answer.
+/// Unset parameters are not sent to the API, so the API defaults apply.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SamplingParameters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+}
+
+impl SamplingParameters {
+    /// Returns a copy of these parameters where every parameter set in `overrides` is replaced.
+    pub fn merged_with(&self, overrides: &SamplingParameters) -> SamplingParameters {
+        SamplingParameters {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            stop: overrides.stop.clone().or_else(|| self.stop.clone()),
+            seed: overrides.seed.or(self.seed),
+            presence_penalty: overrides.presence_penalty.or(self.presence_penalty),
+            frequency_penalty: overrides.frequency_penalty.or(self.frequency_penalty),
+        }
+    }
+}
+
+/// A named combination of model and sampling parameters that can be selected with `--preset`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ModelPreset {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
+}
+
+fn default_presets() -> BTreeMap<String, ModelPreset> {
+    let mut presets = BTreeMap::new();
+    presets.insert("fast".to_string(), ModelPreset {
+        model: Some("gpt-4o-mini".to_string()),
+        sampling: SamplingParameters {
+            temperature: Some(0.0),
+            ..SamplingParameters::default()
+        },
+    });
+    presets.insert("creative".to_string(), ModelPreset {
+        model: None,
+        sampling: SamplingParameters {
+            temperature: Some(1.0),
+            presence_penalty: Some(0.5),
+            ..SamplingParameters::default()
+        },
+    });
+    presets
+}
+
+/// Settings for the HTTP layer that is shared by all generator backends.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpConfig {
+    pub timeout_seconds: u64,
+    pub max_retries: u32,
+    pub initial_backoff_millis: u64,
+    pub max_backoff_millis: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            timeout_seconds: 120,
+            max_retries: 4,
+            initial_backoff_millis: 1000,
+            max_backoff_millis: 30000,
+        }
+    }
+}
+
+
+/// Limits how many tokens the context blocks may take up in the prompt.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ContextBudgetConfig {
+    /// Maximum number of prompt tokens. Defaults to the model's context window minus `reserved_completion_tokens`.
+    pub max_tokens: Option<usize>,
+    pub reserved_completion_tokens: usize,
+    pub strategy: TruncationStrategy,
+}
+
+impl Default for ContextBudgetConfig {
+    fn default() -> Self {
+        ContextBudgetConfig {
+            max_tokens: None,
+            reserved_completion_tokens: 1024,
+            strategy: TruncationStrategy::Middle,
+        }
+    }
+}
+
+/// Controls how many few-shot examples are added to the prompt.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExamplesConfig {
+    pub enabled: bool,
+    pub max_count: usize,
+    pub max_tokens: usize,
+}
+
+impl Default for ExamplesConfig {
+    fn default() -> Self {
+        ExamplesConfig {
+            enabled: true,
+            max_count: 3,
+            max_tokens: 1000,
+        }
+    }
+}
+
+/// Controls the automatically detected environment requirement and which items it contains.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EnvironmentConfig {
+    pub enabled: bool,
+    /// How long the detected environment is reused, e.g. `12h` or `7d`.
+    pub cache_ttl: String,
+    pub distribution: bool,
+    pub shell: bool,
+    pub coreutils: bool,
+    pub package_managers: bool,
+    pub container: bool,
+    pub wsl: bool,
+    pub ssh: bool,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        EnvironmentConfig {
+            enabled: true,
+            cache_ttl: "1d".to_string(),
+            distribution: true,
+            shell: true,
+            coreutils: true,
+            package_managers: true,
+            container: true,
+            wsl: true,
+            ssh: true,
+        }
+    }
+}
+
+/// A named set of config keys that override the config files, e.g. for work and personal projects.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    /// Globs of directories in which the profile is used automatically, with their subdirectories (e.g. `~/work`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub directories: Vec<String>,
+    /// Any config keys except `profiles`.
+    #[serde(flatten)]
+    pub overrides: serde_yaml::Mapping,
+}
+
+/// Settings of the built-in context providers (`-c @tools`, `-c @env`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProvidersConfig {
+    /// The tools whose versions `@tools` reports.
+    pub tools: Vec<String>,
+    /// The environment variables `@env` reports. Others are never sent to the model.
+    pub env_allowlist: Vec<String>,
+}
+
+impl Default for ProvidersConfig {
+    fn default() -> Self {
+        let strings = |items: &[&str]| items.iter().map(|s| s.to_string()).collect();
+        ProvidersConfig {
+            tools: strings(&["git", "docker", "kubectl", "python3", "node", "npm", "cargo", "go", "java", "jq", "curl"]),
+            env_allowlist: strings(&["SHELL", "TERM", "LANG", "EDITOR", "HOME", "USER", "PATH", "VIRTUAL_ENV",
+                "CONDA_DEFAULT_ENV", "KUBECONFIG", "AWS_PROFILE", "AWS_REGION"]),
+        }
+    }
+}
+
+/// Limits for input that is passed to houston directly, e.g. piped via stdin.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InputConfig {
+    /// Piped input longer than this is shortened in the middle.
+    pub stdin_max_tokens: usize,
+    /// Whether to remove likely secrets (tokens, passwords, private keys) before sending input to the model.
+    pub redact: bool,
+    /// Files passed via `--file` that are larger than this are skipped.
+    pub max_file_bytes: u64,
+    /// The total size of the files passed via `--file`, and of each listing of `--dir`.
+    pub files_max_tokens: usize,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            stdin_max_tokens: 4000,
+            redact: true,
+            max_file_bytes: 100_000,
+            files_max_tokens: 8000,
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StrictOpenAiConfig {
-    pub api_key: ApiKey,
+    pub api_key: ApiKeySource,
     pub model: String,
+    pub base_url: String,
+    pub sampling: SamplingParameters,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -80,24 +392,62 @@ pub struct StrictUserConfig {
     pub default_context_shell: String,
     pub default_run_mode: RunMode,
     pub open_ai: StrictOpenAiConfig,
+    pub http: HttpConfig,
+    pub context_budget: ContextBudgetConfig,
+    pub pricing: BTreeMap<String, ModelPrice>,
+    pub presets: BTreeMap<String, ModelPreset>,
+    pub examples: ExamplesConfig,
+    pub interpreters: BTreeMap<String, Interpreter>,
+    pub shebang_policy: ShebangPolicy,
+    pub input: InputConfig,
+    pub providers: ProvidersConfig,
+    pub environment: EnvironmentConfig,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase", default)]
 pub struct UserConfig {
+    /// The version of the config format, see [crate::migration].
+    version: u32,
     pub(crate) default_shell: Option<String>,
     pub(crate) default_context_shell: Option<String>,
     pub(crate) default_run_mode: RunMode,
     open_ai: OpenAiConfig,
+    http: HttpConfig,
+    context_budget: ContextBudgetConfig,
+    /// Prices per model, used to estimate the cost of each invocation.
+    pricing: BTreeMap<String, ModelPrice>,
+    presets: BTreeMap<String, ModelPreset>,
+    examples: ExamplesConfig,
+    /// Additional interpreters, or replacements for the built-in ones.
+    interpreters: BTreeMap<String, Interpreter>,
+    shebang_policy: ShebangPolicy,
+    input: InputConfig,
+    providers: ProvidersConfig,
+    environment: EnvironmentConfig,
+    /// Named sets of overrides, selected with `--profile`, `HOUSTON_PROFILE` or by the working directory.
+    profiles: BTreeMap<String, Profile>,
 }
 
 impl Default for UserConfig {
     fn default() -> Self {
         UserConfig {
+            version: CONFIG_VERSION,
             default_shell: None,
             default_context_shell: None,
             default_run_mode: RunMode::Ask,
             open_ai: OpenAiConfig::default(),
+            http: HttpConfig::default(),
+            context_budget: ContextBudgetConfig::default(),
+            pricing: default_price_table(),
+            presets: default_presets(),
+            examples: ExamplesConfig::default(),
+            interpreters: BTreeMap::new(),
+            shebang_policy: ShebangPolicy::default(),
+            input: InputConfig::default(),
+            providers: ProvidersConfig::default(),
+            environment: EnvironmentConfig::default(),
+            profiles: BTreeMap::new(),
         }
     }
 }
@@ -110,23 +460,15 @@ impl UserConfig {
     /// The fields that are resolved if not present are:
     /// - default_shell
     /// - default_context_shell
-    /// - open_ai.api_key
+    ///
+    /// The API key is resolved later, see [ApiKeySource].
     fn to_strict(&self) -> Result<StrictUserConfig, String> {
-        // load open ai key from env var if not present in config
-        let api_key = match &self.open_ai.api_key {
-            Some(api_key) => api_key.0.clone(),
-            None => {
-                let open_ai_key = std::env::var("OPENAI_API_KEY").ok();
-                if open_ai_key.is_none() {
-                    return Err("OPENAI_API_KEY is not set".to_string());
-                }
-                open_ai_key.unwrap()
-            }
-        };
-
         let open_ai = StrictOpenAiConfig {
-            api_key: ApiKey(api_key),
+            api_key: self.open_ai.api_key_source(),
             model: self.open_ai.model.clone(),
+            base_url: self.open_ai.base_url.clone(),
+            // flattened fields don't fall back to the defaults of the containing struct
+            sampling: default_sampling_parameters().merged_with(&self.open_ai.sampling),
         };
 
         let default_shell = match &self.default_shell {
@@ -145,20 +487,96 @@ impl UserConfig {
             default_context_shell,
             default_run_mode: self.default_run_mode.clone(),
             open_ai,
+            http: self.http.clone(),
+            context_budget: self.context_budget.clone(),
+            pricing: self.pricing.clone(),
+            presets: default_presets().into_iter().chain(self.presets.clone()).collect(),
+            examples: self.examples.clone(),
+            interpreters: self.interpreters.clone(),
+            shebang_policy: self.shebang_policy,
+            input: self.input.clone(),
+            providers: self.providers.clone(),
+            environment: self.environment.clone(),
         })
     }
 
     fn default_for_system() -> Self {
         let shell = get_default_shell_for_system();
         UserConfig {
+            version: CONFIG_VERSION,
             default_shell: Some(shell.clone()),
             default_context_shell: Some(shell),
             default_run_mode: RunMode::Ask,
             open_ai: OpenAiConfig::default(),
+            http: HttpConfig::default(),
+            context_budget: ContextBudgetConfig::default(),
+            pricing: default_price_table(),
+            presets: default_presets(),
+            examples: ExamplesConfig::default(),
+            interpreters: BTreeMap::new(),
+            shebang_policy: ShebangPolicy::default(),
+            input: InputConfig::default(),
+            providers: ProvidersConfig::default(),
+            environment: EnvironmentConfig::default(),
+            profiles: BTreeMap::new(),
         }
     }
 }
 
+/// Marks a mapping in the schema whose keys are names chosen by the user (e.g. presets), with the schema of its values.
+pub(crate) const SCHEMA_ANY_KEY: &str = "*";
+
+/// All keys the config understands, as a YAML document. Used to find unknown keys and suggest the right ones.
+pub(crate) fn config_schema() -> serde_yaml::Value {
+    use serde_yaml::{Mapping, Value};
+
+    let all_sampling = SamplingParameters {
+        temperature: Some(0.0),
+        top_p: Some(0.0),
+        max_tokens: Some(0),
+        stop: Some(vec![]),
+        seed: Some(0),
+        presence_penalty: Some(0.0),
+        frequency_penalty: Some(0.0),
+    };
+    let preset = ModelPreset { model: Some(String::new()), sampling: all_sampling.clone() };
+    let price = ModelPrice { prompt_per_million: 0.0, completion_per_million: 0.0 };
+    let interpreter = Interpreter {
+        command: vec![],
+        suffix: String::new(),
+        args: Default::default(),
+        aliases: vec![],
+    };
+    let any_key = |value: Value| {
+        let mut mapping = Mapping::new();
+        mapping.insert(Value::String(SCHEMA_ANY_KEY.to_string()), value);
+        Value::Mapping(mapping)
+    };
+
+    fn to_value<T: Serialize>(value: &T) -> Value {
+        serde_yaml::to_value(value).expect("config serializes to YAML")
+    }
+    let mut schema = to_value(&UserConfig::default_for_system());
+    schema["openAi"] = to_value(&OpenAiConfig {
+        api_key: Some(ApiKey(String::new())),
+        api_key_file: Some(String::new()),
+        api_key_command: Some(String::new()),
+        sampling: all_sampling,
+        ..OpenAiConfig::default()
+    });
+    schema["presets"] = any_key(to_value(&preset));
+    schema["pricing"] = any_key(to_value(&price));
+    schema["interpreters"] = any_key(to_value(&interpreter));
+    // a profile can override every key, except the profiles
+    let mut profile = schema.clone();
+    if let Value::Mapping(profile) = &mut profile {
+        profile.remove("profiles");
+        profile.insert(Value::String("directories".to_string()), Value::Sequence(vec![]));
+    }
+    schema["profiles"] = any_key(profile);
+    schema
+}
+
 fn get_default_shell_for_system() -> String {
     let os = std::env::consts::OS;
     match os {
@@ -179,39 +597,248 @@ pub fn get_houston_dir() -> std::path::PathBuf {
     xdg_config_home.join(CONFIG_DIR_NAME)
 }
 
-/// load user config from the default config file location
-pub fn load_user_config() -> UserConfig {
-    let config_path = get_houston_dir().join(CONFIG_FILE_NAME);
+/// Finds the nearest `.houston` directory in the current working directory or one of its parents.
+pub fn find_project_dir() -> Option<std::path::PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(PROJECT_DIR_NAME))
+        .find(|dir| dir.is_dir())
+}
+
+pub fn user_config_path() -> std::path::PathBuf {
+    get_houston_dir().join(CONFIG_FILE_NAME)
+}
+
+/// The config of the nearest `.houston` directory, if there is one.
+pub fn project_config_path() -> Option<std::path::PathBuf> {
+    find_project_dir().map(|dir| dir.join(CONFIG_FILE_NAME))
+}
+
+/// Whether the file is a project config, which may only set some keys (see [crate::config_file::validate_project]).
+pub fn is_project_config(path: &Path) -> bool {
+    path.parent().and_then(|dir| dir.file_name()).is_some_and(|name| name == PROJECT_DIR_NAME)
+}
+
+/// The system, user and project config files, in the order in which they are applied.
+pub fn config_file_paths() -> Vec<std::path::PathBuf> {
+    let mut paths = vec![system_config_path(), user_config_path()];
+    paths.extend(project_config_path());
+    paths
+}
+
+/// A warning for every config file that holds an API key and can be read by other users.
+pub fn api_key_permission_warnings() -> Vec<String> {
+    config_file_paths().iter()
+        .filter(|path| std::fs::read_to_string(path).ok()
+            .and_then(|text| serde_yaml::from_str::<serde_yaml::Value>(&text).ok())
+            .is_some_and(|value| contains_api_key(&value)))
+        .filter_map(|path| readable_by_others(path).map(|mode| format!(
+            "{} contains an API key and is readable by other users (mode {:o}). \
+             Restrict it with `chmod 600 {}`, or use openAi.apiKeyFile or openAi.apiKeyCommand instead.",
+            path.display(), mode, path.display())))
+        .collect()
+}
+
+/// `HOUSTON_*` variables that do not name a config key. They are ignored when the config is loaded
+/// (a variable may be meant for another version of houston), and reported as errors by `hu config validate`.
+pub fn env_var_warnings() -> Vec<String> {
+    env_layers(std::env::vars()).into_iter()
+        .flat_map(|layer| unknown_keys(&layer.value).into_iter()
+            .map(move |problem| format!("{}: {}, the variable is ignored", layer.origin, problem)))
+        .collect()
+}
 
-    if !config_path.exists() {
-        return UserConfig::default();
+/// Replaces a leading `~` with the home directory.
+pub(crate) fn expand_home(path: &str, home: Option<&Path>) -> String {
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", home.display(), rest),
+        _ => path.to_string(),
     }
+}
 
-    let config_file = File::open(config_path).unwrap();
-    let config: UserConfig = serde_yaml::from_reader(config_file).unwrap();
+/// Loads all config layers and merges them. Precedence, from lowest to highest:
+/// defaults, the system config, the user config, the project config, the selected profile
+/// and `HOUSTON_*` environment variables. (Command line options override the result when they are applied.)
+/// `profile` is the name given with `--profile`.
+pub fn load_merged_config(profile: Option<&str>) -> Result<MergedConfig, String> {
+    let defaults = serde_yaml::to_value(UserConfig::default_for_system()).map_err(|e| e.to_string())?;
+    let mut layers = vec![ConfigLayer { origin: ConfigOrigin::Default, value: defaults }];
+
+    let system = system_config_path();
+    layers.extend(ConfigLayer::from_file(&system, ConfigOrigin::System(system.clone()))?);
+    let user = user_config_path();
+    layers.extend(ConfigLayer::from_file(&user, ConfigOrigin::User(user.clone()))?);
+    if let Some(project) = project_config_path() {
+        layers.extend(ConfigLayer::from_file(&project, ConfigOrigin::Project(project.clone()))?);
+    }
+    let profile_position = layers.len();
+    for layer in env_layers(std::env::vars()) {
+        // unknown variables are ignored, see env_var_warnings
+        if !unknown_keys(&layer.value).is_empty() {
+            continue;
+        }
+        let problems = validate_value(&layer.value);
+        if !problems.is_empty() {
+            return Err(format_problems(&layer.origin.to_string(), &problems));
+        }
+        layers.push(layer);
+    }
 
-    config
+    // profiles can be defined in any layer but the project config
+    let merged = merge(&layers);
+    let merged = match profile_layer(&merged.value, profile)? {
+        Some(layer) => {
+            layers.insert(profile_position, layer);
+            merge(&layers)
+        }
+        None => merged,
+    };
+    check_api_key_sources(&merged)?;
+    Ok(merged)
+}
+
+/// `apiKeyFile` and `apiKeyCommand` are read (or run) on every invocation, so they are only taken from
+/// the system and user config, profiles (which are defined there) and `HOUSTON_*` variables.
+pub(crate) fn check_api_key_sources(merged: &MergedConfig) -> Result<(), String> {
+    for key in ["apiKeyFile", "apiKeyCommand"] {
+        let path = vec!["openAi".to_string(), key.to_string()];
+        if let Some(origin @ ConfigOrigin::Project(_)) = merged.origins.get(&path) {
+            return Err(format!("openAi.{} comes from {}, but it is only read from the system or user config \
+                                and HOUSTON_* variables", key, origin));
+        }
+    }
+    Ok(())
+}
+
+/// load the user config from all config layers
+pub fn load_user_config(profile: Option<&str>) -> Result<UserConfig, String> {
+    let merged = load_merged_config(profile)?;
+    serde_yaml::from_value(merged.value).map_err(|e| format!("Invalid configuration: {}", e))
 }
 
 
 /// load user config and convert it to a strict version
 /// for that it will resolve all the required fields that are not set in the user config
 /// either from environment variables or from the system.
-pub fn load_user_config_strict() -> Result<StrictUserConfig, String> {
-    let user_config = load_user_config();
+pub fn load_user_config_strict(profile: Option<&str>) -> Result<StrictUserConfig, String> {
+    let user_config = load_user_config(profile)?;
     user_config.to_strict()
 }
 
+/// Creates a user config with the shells of the system.
+/// It only contains those, so that the system and default configs still apply to everything else.
 pub fn create_user_config_if_not_exists() {
-    let config_path = get_houston_dir().join(CONFIG_FILE_NAME);
+    let config_path = user_config_path();
 
     if config_path.exists() {
         return;
     }
 
     let default_config = UserConfig::default_for_system();
-    let default_config_str = serde_yaml::to_string(&default_config).unwrap();
+    let default_config_str = format!(
+        "# All settings are listed in example-config.yml, `hu config show --origin` prints the effective ones.\n\
+         version: {}\ndefaultShell: {}\ndefaultContextShell: {}\n",
+        CONFIG_VERSION,
+        default_config.default_shell.unwrap_or_default(), default_config.default_context_shell.unwrap_or_default());
 
     std::fs::create_dir_all(get_houston_dir()).unwrap();
     std::fs::write(config_path, default_config_str).unwrap();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_key_sources_are_not_taken_from_a_project_config() {
+        let layer = |yaml: &str, origin: ConfigOrigin| ConfigLayer { origin, value: serde_yaml::from_str(yaml).unwrap() };
+        let user = layer("openAi:\n  apiKeyCommand: pass show openai\n", ConfigOrigin::User("config.yml".into()));
+        let project = layer("openAi:\n  apiKeyFile: key.txt\n", ConfigOrigin::Project(".houston/config.yml".into()));
+
+        assert!(check_api_key_sources(&merge(std::slice::from_ref(&user))).is_ok());
+        let err = check_api_key_sources(&merge(&[user, project])).unwrap_err();
+        assert!(err.starts_with("openAi.apiKeyFile comes from project (.houston/config.yml)"), "{}", err);
+    }
+
+    #[test]
+    fn test_sampling_parameters_merged_with() {
+        let base = SamplingParameters {
+            temperature: Some(0.5),
+            top_p: Some(1.0),
+            stop: Some(vec!["END".to_string()]),
+            ..SamplingParameters::default()
+        };
+        let overrides = SamplingParameters {
+            temperature: Some(0.0),
+            seed: Some(42),
+            ..SamplingParameters::default()
+        };
+
+        let merged = base.merged_with(&overrides);
+
+        assert_eq!(merged.temperature, Some(0.0));
+        assert_eq!(merged.top_p, Some(1.0));
+        assert_eq!(merged.seed, Some(42));
+        assert_eq!(merged.stop, Some(vec!["END".to_string()]));
+    }
+
+    #[test]
+    fn test_obfuscate_api_key() {
+        assert_eq!(obfuscate_api_key("sk-proj-abcdefghijklmnopqrstuvwxyz"), "sk-pro****");
+        assert_eq!(obfuscate_api_key("sk-1"), "****");
+        assert_eq!(obfuscate_api_key(""), "****");
+        assert_eq!(obfuscate_api_key("ключключключключключ"), "ключкл****");
+
+        let key = ApiKey("sk-proj-abcdefghijklmnopqrstuvwxyz".to_string());
+        assert_eq!(format!("{:?}", key), "ApiKey(\"sk-pro****\")");
+        assert_eq!(key.hide_in("Incorrect API key provided: sk-proj-abcdefghijklmnopqrstuvwxyz."),
+                   "Incorrect API key provided: sk-pro****.");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_api_key_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("key");
+        std::fs::write(&path, "sk-file\n").unwrap();
+        std::fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o600)).unwrap();
+
+        let config = OpenAiConfig {
+            api_key_file: Some(path.display().to_string()),
+            api_key_command: Some("echo sk-command".to_string()),
+            ..OpenAiConfig::default()
+        };
+        assert_eq!(config.api_key_source().resolve().unwrap().0, "sk-file");
+
+        let config = OpenAiConfig { api_key_file: None, ..config };
+        assert_eq!(config.api_key_source().resolve().unwrap().0, "sk-command");
+
+        let config = OpenAiConfig { api_key: Some(ApiKey("sk-config".to_string())), ..config };
+        assert_eq!(config.api_key_source().resolve().unwrap().0, "sk-config");
+    }
+
+    #[test]
+    fn test_parse_sampling_and_presets() {
+        let yaml = "openAi:\n  temperature: 0.2\n  maxTokens: 100\npresets:\n  repro:\n    temperature: 0\n    seed: 7\n";
+
+        let config: UserConfig = serde_yaml::from_str(yaml).unwrap();
+
+        assert_eq!(config.open_ai.model, DEFAULT_CHAT_MODEL);
+        assert_eq!(config.open_ai.sampling.temperature, Some(0.2));
+        assert_eq!(config.open_ai.sampling.max_tokens, Some(100));
+        assert_eq!(config.open_ai.sampling.top_p, None);
+        let with_key = UserConfig {
+            open_ai: OpenAiConfig { api_key: Some(ApiKey("sk-123456".to_string())), ..config.open_ai },
+            presets: config.presets.clone(),
+            ..UserConfig::default()
+        };
+        let strict = with_key.to_strict().unwrap();
+        assert_eq!(strict.open_ai.sampling.temperature, Some(0.2));
+        assert_eq!(strict.open_ai.sampling.top_p, Some(1.0));
+        assert!(strict.presets.contains_key("fast"));
+        assert!(strict.presets.contains_key("repro"));
+        let preset = &config.presets["repro"];
+        assert_eq!(preset.model, None);
+        assert_eq!(preset.sampling.seed, Some(7));
+    }
+}
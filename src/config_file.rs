@@ -0,0 +1,665 @@
+use std::fmt;
+
+use serde_yaml::{Mapping, Value};
+
+use crate::config::{config_schema, SCHEMA_ANY_KEY, UserConfig};
+use crate::migration::{CONFIG_VERSION, migrate_value, migrations, version_of};
+
+/// Something wrong in a config file, e.g. an unknown key or a value of the wrong type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigProblem {
+    /// 1-based, if the problem can be located.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Checks the text of a config file: YAML syntax, unknown keys, enum values and types.
+pub fn validate(text: &str) -> Vec<ConfigProblem> {
+    let value: Value = match serde_yaml::from_str(text) {
+        Ok(value) => value,
+        Err(e) => return vec![problem_from_yaml_error(&e)],
+    };
+    match version_of(&value) {
+        Err(message) => return vec![ConfigProblem { line: line_of_key(text, &["version".to_string()]), message }],
+        Ok(version) if version < CONFIG_VERSION => {
+            // an older config is valid if it is valid after the migration, which may move keys
+            let mut migrated = value.clone();
+            if let Err(message) = migrate_value(&mut migrated, &migrations()) {
+                return vec![ConfigProblem { line: None, message }];
+            }
+            if let Value::Mapping(mapping) = &mut migrated {
+                mapping.remove("version");
+            }
+            if migrated != value {
+                return validate_value(&migrated);
+            }
+        }
+        Ok(_) => {}
+    }
+    let mut problems = unknown_key_problems(&value).into_iter()
+        .map(|(path, message)| ConfigProblem { line: line_of_key(text, &path), message })
+        .collect::<Vec<ConfigProblem>>();
+    if let Err(e) = serde_yaml::from_str::<UserConfig>(text) {
+        problems.push(problem_from_yaml_error(&e));
+    }
+    problems.extend(profile_problems(&value).into_iter()
+        .map(|(path, message)| ConfigProblem { line: line_of_key(text, &path), message }));
+    problems.sort_by_key(|p| p.line);
+    problems
+}
+
+/// Like [validate], for config that does not come from a file (e.g. environment variables).
+pub fn validate_value(value: &Value) -> Vec<ConfigProblem> {
+    let mut problems = unknown_key_problems(value).into_iter()
+        .map(|(_, message)| ConfigProblem { line: None, message })
+        .collect::<Vec<ConfigProblem>>();
+    if let Err(e) = serde_yaml::from_value::<UserConfig>(value.clone()) {
+        problems.push(ConfigProblem { line: None, message: e.to_string() });
+    }
+    problems.extend(profile_problems(value).into_iter().map(|(_, message)| ConfigProblem { line: None, message }));
+    problems
+}
+
+/// The keys a project config (`.houston/config.yml`) may set. The API endpoint and key, interpreters, profiles
+/// and the like are only read from the system and user config, so that a cloned repository cannot send
+/// the API key elsewhere or run commands.
+pub const PROJECT_KEYS: [&str; 6] = ["version", "defaultShell", "defaultRunMode", "contextBudget", "examples", "environment"];
+
+/// Like [validate], for a project config, which may only set the [PROJECT_KEYS].
+/// It also cannot make scripts run without asking, or name a shell by its path.
+pub fn validate_project(text: &str) -> Vec<ConfigProblem> {
+    let mut problems = validate(text);
+    if let Ok(value) = serde_yaml::from_str::<Value>(text) {
+        problems.extend(project_problems(&value).into_iter()
+            .map(|(path, message)| ConfigProblem { line: line_of_key(text, &path), message }));
+        problems.sort_by_key(|p| p.line);
+    }
+    problems
+}
+
+fn project_problems(value: &Value) -> Vec<(Vec<String>, String)> {
+    let Some(mapping) = value.as_mapping() else {
+        return vec![];
+    };
+    mapping.iter().filter_map(|(key, value)| {
+        let key = key.as_str().map(|k| k.to_string()).unwrap_or_else(|| format_key(key));
+        let message = if !PROJECT_KEYS.contains(&key.as_str()) {
+            format!("{} cannot be set in a project config, only in the user or system config", key)
+        } else if key == "defaultRunMode" && value.as_str() == Some("force") {
+            "defaultRunMode: force cannot be set in a project config, only in the user or system config".to_string()
+        } else if key == "defaultShell" && value.as_str().is_some_and(|shell| shell.contains(['/', '\\'])) {
+            "defaultShell must be the name of a shell in a project config, not a path".to_string()
+        } else {
+            return None;
+        };
+        Some((vec![key], message))
+    }).collect()
+}
+
+/// Wrong types and values in the overrides of the profiles, which are only checked for unknown keys otherwise.
+fn profile_problems(value: &Value) -> Vec<(Vec<String>, String)> {
+    let Some(Value::Mapping(profiles)) = value.get("profiles") else {
+        return vec![];
+    };
+    profiles.iter().filter_map(|(name, profile)| {
+        let profile = profile.as_mapping()?;
+        let overrides = profile.iter()
+            .filter(|(key, _)| key.as_str() != Some("directories"))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<Mapping>();
+        let e = serde_yaml::from_value::<UserConfig>(Value::Mapping(overrides)).err()?;
+        let name = name.as_str().map(|n| n.to_string()).unwrap_or_else(|| format_key(name));
+        let message = format!("profiles.{}: {}", name, e);
+        Some((vec!["profiles".to_string(), name], message))
+    }).collect()
+}
+
+fn problem_from_yaml_error(e: &serde_yaml::Error) -> ConfigProblem {
+    let message = e.to_string();
+    // the line is reported separately
+    let message = match message.rfind(" at line ") {
+        Some(i) if e.location().is_some() => message[..i].to_string(),
+        _ => message,
+    };
+    ConfigProblem { line: e.location().map(|l| l.line()), message }
+}
+
+/// The unknown keys in the value, e.g. `unknown key openAi.modle (did you mean model?)`.
+pub fn unknown_keys(value: &Value) -> Vec<String> {
+    unknown_key_problems(value).into_iter().map(|(_, message)| message).collect()
+}
+
+fn unknown_key_problems(value: &Value) -> Vec<(Vec<String>, String)> {
+    let mut problems = Vec::new();
+    collect_unknown_keys(value, &config_schema(), &mut vec![], &mut problems);
+    problems
+}
+
+fn collect_unknown_keys(value: &Value, schema: &Value, path: &mut Vec<String>,
+                        problems: &mut Vec<(Vec<String>, String)>) {
+    let (Value::Mapping(mapping), Value::Mapping(schema_mapping)) = (value, schema) else {
+        return;
+    };
+    for (key, value) in mapping {
+        let key = key.as_str().map(|k| k.to_string()).unwrap_or_else(|| format_key(key));
+        path.push(key.clone());
+        let child_schema = schema_mapping.get(key.as_str()).or_else(|| schema_mapping.get(SCHEMA_ANY_KEY));
+        match child_schema {
+            Some(child_schema) => collect_unknown_keys(value, child_schema, path, problems),
+            None => {
+                let known = schema_mapping.keys().filter_map(|k| k.as_str()).collect::<Vec<&str>>();
+                let message = match suggest(&key, &known) {
+                    Some(suggestion) => format!("unknown key {} (did you mean {}?)", path.join("."), suggestion),
+                    None => format!("unknown key {}", path.join(".")),
+                };
+                problems.push((path.clone(), message));
+            }
+        }
+        path.pop();
+    }
+}
+
+fn format_key(key: &Value) -> String {
+    serde_yaml::to_string(key).unwrap_or_default().trim().to_string()
+}
+
+/// The most similar of the known keys, if one is close enough to be a typo.
+pub fn suggest<'a>(key: &str, known: &[&'a str]) -> Option<&'a str> {
+    known.iter()
+        .map(|k| (strsim::levenshtein(&key.to_lowercase(), &k.to_lowercase()), *k))
+        .filter(|(distance, k)| *distance <= (k.len() / 3).max(2))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, k)| k)
+}
+
+/// Finds the line of a key in block-style YAML by following the indentation of its parents.
+fn line_of_key(text: &str, path: &[String]) -> Option<usize> {
+    let lines = text.lines().collect::<Vec<&str>>();
+    let mut start = 0;
+    let mut parent_indent: Option<usize> = None;
+    let mut found = None;
+    for key in path {
+        let mut hit = None;
+        for (i, line) in lines.iter().enumerate().skip(start) {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let indent = line.len() - trimmed.len();
+            match parent_indent {
+                Some(parent) if indent <= parent => break,
+                None if indent > 0 => continue,
+                _ => {}
+            }
+            let name = trimmed.split(':').next().unwrap_or_default().trim().trim_matches(['"', '\'']);
+            if name == key && trimmed.contains(':') {
+                hit = Some((i, indent));
+                break;
+            }
+        }
+        let (i, indent) = hit?;
+        start = i + 1;
+        parent_indent = Some(indent);
+        found = Some(i + 1);
+    }
+    found
+}
+
+/// Splits a dotted key into the keys of the document. Parts are joined where the document has a key
+/// with a dot in it, e.g. `pricing.gpt-4.1.promptPerMillion`.
+pub fn key_path(value: &Value, key: &str) -> Vec<String> {
+    let parts = key.split('.').collect::<Vec<&str>>();
+    let mut path = Vec::new();
+    let mut current = Some(value);
+    let mut i = 0;
+    while i < parts.len() {
+        let mapping = current.and_then(|v| v.as_mapping());
+        let longest = (i + 1..=parts.len()).rev()
+            .find(|&end| mapping.is_some_and(|m| m.contains_key(parts[i..end].join("."))))
+            .unwrap_or(i + 1);
+        let segment = parts[i..longest].join(".");
+        current = mapping.and_then(|m| m.get(segment.as_str()));
+        path.push(segment);
+        i = longest;
+    }
+    path
+}
+
+/// Splits a dotted key by the keys the config understands, for keys that are not in a document yet.
+/// Under keys with free names (e.g. model names in `pricing`) the shortest name that leaves
+/// a known rest is used, so `pricing.gpt-4.1.promptPerMillion` has the model `gpt-4.1`.
+pub fn schema_key_path(key: &str) -> Option<Vec<String>> {
+    fn resolve(parts: &[&str], schema: &Value) -> Option<Vec<String>> {
+        if parts.is_empty() {
+            return Some(vec![]);
+        }
+        let mapping = schema.as_mapping()?;
+        if let Some(child) = mapping.get(parts[0]) {
+            let mut path = resolve(&parts[1..], child)?;
+            path.insert(0, parts[0].to_string());
+            return Some(path);
+        }
+        let child = mapping.get(SCHEMA_ANY_KEY)?;
+        // a name does not swallow keys of its value, e.g. `work.openAi` is not a profile name
+        let names = (1..=parts.len())
+            .take_while(|&end| end == 1 || child.get(parts[end - 1]).is_none());
+        names.into_iter().find_map(|end| {
+            let mut path = resolve(&parts[end..], child)?;
+            path.insert(0, parts[..end].join("."));
+            Some(path)
+        })
+    }
+    resolve(&key.split('.').collect::<Vec<&str>>(), &config_schema())
+}
+
+/// The value of a dotted key, e.g. `openAi.model`.
+pub fn get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key_path(value, key).iter().try_fold(value, |v, k| v.get(k.as_str()))
+}
+
+/// Sets the value at the path, creating the mappings on the way.
+pub fn set(value: &mut Value, path: &[String], new_value: Value) {
+    let mut current = value;
+    for k in path {
+        if !current.is_mapping() {
+            *current = Value::Mapping(Mapping::new());
+        }
+        let mapping = current.as_mapping_mut().expect("just made a mapping");
+        current = mapping.entry(Value::String(k.clone())).or_insert(Value::Null);
+    }
+    *current = new_value;
+}
+
+/// Removes the value at the path and the mappings that become empty. Returns whether it was set.
+pub fn unset(value: &mut Value, path: &[String]) -> bool {
+    fn remove(value: &mut Value, path: &[String]) -> bool {
+        let Some(mapping) = value.as_mapping_mut() else {
+            return false;
+        };
+        if path.len() == 1 {
+            return mapping.remove(path[0].as_str()).is_some();
+        }
+        let Some(child) = mapping.get_mut(path[0].as_str()) else {
+            return false;
+        };
+        let removed = remove(child, &path[1..]);
+        if child.as_mapping().is_some_and(|m| m.is_empty()) {
+            mapping.remove(path[0].as_str());
+        }
+        removed
+    }
+    remove(value, path)
+}
+
+/// An error for a key path that the config does not have, with a suggestion, or `None` if the key is known.
+pub fn check_key(path: &[String]) -> Option<String> {
+    let mut value = Value::Mapping(Mapping::new());
+    set(&mut value, path, Value::Null);
+    unknown_key_problems(&value).into_iter().next().map(|(_, message)| message)
+}
+
+/// Whether the text of a config file has comments, which are lost if the file is rewritten from its value.
+pub fn has_comments(text: &str) -> bool {
+    text.lines().any(|line| line.trim_start().starts_with('#') || line.contains(" #"))
+}
+
+/// The text of a config file changed line by line so that it holds `document`, keeping its comments
+/// and layout. `None` if that is not possible, e.g. for flow style like `openAi: {model: gpt-4o}`.
+pub fn edit_text(text: &str, document: &Value) -> Option<String> {
+    let original = as_mapping(serde_yaml::from_str(text).ok()?);
+    let mut lines = text.lines().map(|line| line.to_string()).collect::<Vec<String>>();
+
+    for path in leaf_paths(document) {
+        let value = value_at(document, &path)?;
+        if value_at(&original, &path) != Some(value) {
+            set_lines(&mut lines, &path, value)?;
+        }
+    }
+    for path in leaf_paths(&original) {
+        if value_at(document, &path).is_none() {
+            remove_lines(&mut lines, &path)?;
+        }
+    }
+
+    let mut edited = lines.join("\n");
+    edited.push('\n');
+    let parsed = as_mapping(serde_yaml::from_str(&edited).ok()?);
+    (parsed == *document).then_some(edited)
+}
+
+fn as_mapping(value: Value) -> Value {
+    if value.is_null() { Value::Mapping(Mapping::new()) } else { value }
+}
+
+fn value_at<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |v, k| v.get(k.as_str()))
+}
+
+/// The paths of all leaf values (scalars, sequences and empty mappings).
+fn leaf_paths(value: &Value) -> Vec<Vec<String>> {
+    match value {
+        Value::Mapping(mapping) if !mapping.is_empty() => mapping.iter()
+            .filter_map(|(key, value)| Some((key.as_str()?, value)))
+            .flat_map(|(key, value)| leaf_paths(value).into_iter().map(move |mut path| {
+                path.insert(0, key.to_string());
+                path
+            }))
+            .collect(),
+        _ => vec![vec![]],
+    }
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Lines that are not blank and not a comment.
+fn is_content(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+/// The key of a `key: value` line and the position after its colon.
+fn key_of(line: &str) -> Option<(String, usize)> {
+    let indent = indent_of(line);
+    let trimmed = &line[indent..];
+    if trimmed.starts_with('-') || trimmed.starts_with('#') {
+        return None;
+    }
+    let (colon, _) = trimmed.char_indices()
+        .find(|&(i, c)| c == ':' && (trimmed[i + 1..].is_empty() || trimmed[i + 1..].starts_with(' ')))?;
+    let key = trimmed[..colon].trim();
+    let key = key.strip_prefix('"').and_then(|k| k.strip_suffix('"'))
+        .or_else(|| key.strip_prefix('\'').and_then(|k| k.strip_suffix('\'')))
+        .unwrap_or(key);
+    Some((key.to_string(), indent + colon + 1))
+}
+
+/// The index after the last content line of the value of the key in line `start`. Sequences may be indented
+/// as deep as their key.
+fn block_end(lines: &[String], start: usize) -> usize {
+    let indent = indent_of(&lines[start]);
+    let mut end = start + 1;
+    for (i, line) in lines.iter().enumerate().skip(start + 1) {
+        if !is_content(line) {
+            continue;
+        }
+        let line_indent = indent_of(line);
+        if line_indent > indent || (line_indent == indent && line.trim_start().starts_with('-')) {
+            end = i + 1;
+        } else {
+            break;
+        }
+    }
+    end
+}
+
+/// The line of each key of the path, as far as the keys exist.
+fn find_path(lines: &[String], path: &[String]) -> Vec<usize> {
+    let mut found = Vec::new();
+    let (mut from, mut to) = (0, lines.len());
+    for key in path {
+        let Some(level) = lines[from..to].iter().find(|line| is_content(line)).map(|line| indent_of(line)) else {
+            break;
+        };
+        let Some(line) = (from..to).find(|&i| is_content(&lines[i]) && indent_of(&lines[i]) == level
+            && key_of(&lines[i]).is_some_and(|(k, _)| k == *key)) else {
+            break;
+        };
+        found.push(line);
+        (from, to) = (line + 1, block_end(lines, line));
+    }
+    found
+}
+
+/// `key: value`, or `key:` followed by the indented block of the value.
+fn render(key: &str, value: &Value, indent: usize) -> Option<Vec<String>> {
+    let serialized = serde_yaml::to_string(value).ok()?;
+    let serialized = serialized.trim_end();
+    let block = matches!(value, Value::Mapping(m) if !m.is_empty()) || matches!(value, Value::Sequence(s) if !s.is_empty());
+    if !block {
+        // multi-line strings are not edited in place
+        return (!serialized.contains('\n')).then(|| vec![format!("{}{} {}", " ".repeat(indent), key, serialized)]);
+    }
+    let mut lines = vec![format!("{}{}", " ".repeat(indent), key)];
+    lines.extend(serialized.lines().map(|line| format!("{}{}", " ".repeat(indent + 2), line)));
+    Some(lines)
+}
+
+/// `key:`, quoted if necessary.
+fn yaml_key(key: &str) -> String {
+    let key = serde_yaml::to_string(&Value::String(key.to_string())).unwrap_or_default();
+    format!("{}:", key.trim_end())
+}
+
+fn set_lines(lines: &mut Vec<String>, path: &[String], value: &Value) -> Option<()> {
+    let found = find_path(lines, path);
+    if found.len() == path.len() {
+        let line = *found.last()?;
+        let (_, after_colon) = key_of(&lines[line])?;
+        let key = lines[line][indent_of(&lines[line])..after_colon].to_string();
+        let end = block_end(lines, line);
+        lines.splice(line..end, render(&key, value, indent_of(&lines[line]))?);
+        return Some(());
+    }
+
+    // the missing keys go at the end of the deepest existing mapping
+    let (position, indent) = match found.last() {
+        Some(&parent) => {
+            // a scalar value becomes a mapping
+            let (_, after_colon) = key_of(&lines[parent])?;
+            lines[parent].truncate(after_colon);
+            let end = block_end(lines, parent);
+            let indent = lines[parent + 1..end].iter().find(|line| is_content(line))
+                .map_or(indent_of(&lines[parent]) + 2, |line| indent_of(line));
+            (end, indent)
+        }
+        // like in new configs, the version goes first
+        None if path == ["version"] => (lines.iter().position(|line| is_content(line)).unwrap_or(lines.len()), 0),
+        None => (lines.len(), 0),
+    };
+    let mut new_lines = Vec::new();
+    let missing = &path[found.len()..];
+    for (depth, key) in missing[..missing.len() - 1].iter().enumerate() {
+        new_lines.push(format!("{}{}", " ".repeat(indent + 2 * depth), yaml_key(key)));
+    }
+    new_lines.extend(render(&yaml_key(missing.last()?), value, indent + 2 * (missing.len() - 1))?);
+    lines.splice(position..position, new_lines);
+    Some(())
+}
+
+/// Removes the key and the mappings that become empty.
+fn remove_lines(lines: &mut Vec<String>, path: &[String]) -> Option<()> {
+    let found = find_path(lines, path);
+    if found.len() != path.len() {
+        return None;
+    }
+    for &line in found.iter().rev() {
+        let end = block_end(lines, line);
+        let has_children = lines[line + 1..end].iter().any(|l| is_content(l));
+        let has_value = key_of(&lines[line]).is_some_and(|(_, after_colon)| !lines[line][after_colon..].trim().is_empty());
+        if line == *found.last()? || (!has_children && !has_value) {
+            lines.drain(line..end);
+        } else {
+            break;
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edited(text: &str, change: impl FnOnce(&mut Value)) -> Option<String> {
+        let mut document = as_mapping(serde_yaml::from_str(text).unwrap());
+        change(&mut document);
+        edit_text(text, &document)
+    }
+
+    fn path(key: &str) -> Vec<String> {
+        key.split('.').map(|k| k.to_string()).collect()
+    }
+
+    #[test]
+    fn test_edit_text_keeps_comments() {
+        let text = "# my config\nversion: 2\ndefaultShell: bash\nopenAi:\n  # the model for work\n  model: gpt-4\n\n# end\n";
+
+        let edited = edited(text, |document| {
+            set(document, &path("openAi.model"), Value::String("gpt-4o".to_string()));
+            set(document, &path("openAi.temperature"), Value::from(0.2));
+            set(document, &path("examples.enabled"), Value::Bool(false));
+            unset(document, &path("defaultShell"));
+        }).unwrap();
+
+        assert_eq!(edited, "# my config\nversion: 2\nopenAi:\n  # the model for work\n  model: gpt-4o\n  temperature: 0.2\n\n# end\n\
+                            examples:\n  enabled: false\n");
+    }
+
+    #[test]
+    fn test_edit_text_removes_empty_mappings_and_adds_sequences() {
+        let text = "openAi:\n  model: gpt-4 # work\nversion: 2\n";
+
+        let edited = edited(text, |document| {
+            unset(document, &path("openAi.model"));
+            set(document, &path("openAi.stop"), Value::Sequence(vec![Value::String("END".to_string())]));
+        }).unwrap();
+        assert_eq!(edited, "openAi:\n  stop:\n    - END\nversion: 2\n");
+
+        let edited = edited_without_version("defaultShell: bash\n");
+        assert_eq!(edited, format!("version: {}\ndefaultShell: bash\n", CONFIG_VERSION));
+    }
+
+    fn edited_without_version(text: &str) -> String {
+        edited(text, |document| set(document, &path("version"), Value::from(CONFIG_VERSION))).unwrap()
+    }
+
+    #[test]
+    fn test_flow_style_is_not_edited_in_place() {
+        assert!(edited("openAi: {model: gpt-4, temperature: 1}\n", |document| {
+            set(document, &path("openAi.model"), Value::String("gpt-4o".to_string()));
+        }).is_none());
+        assert!(has_comments("a: 1 # why\n"));
+        assert!(!has_comments("a: 1\n"));
+    }
+
+    #[test]
+    fn test_unknown_keys_with_suggestions_and_lines() {
+        let text = "defaultShel: bash\nopenAi:\n  modle: gpt-4\n  temperature: 0.2\npresets:\n  mine:\n    seeed: 1\n";
+
+        let problems = validate(text).into_iter().map(|p| p.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(problems, vec![
+            "line 1: unknown key defaultShel (did you mean defaultShell?)",
+            "line 3: unknown key openAi.modle (did you mean model?)",
+            "line 7: unknown key presets.mine.seeed (did you mean seed?)",
+        ]);
+    }
+
+    #[test]
+    fn test_unknown_keys_of_a_value() {
+        let value: Value = serde_yaml::from_str("openAi:\n  modle: gpt-4\n").unwrap();
+        assert_eq!(unknown_keys(&value), vec!["unknown key openAi.modle (did you mean model?)"]);
+        assert!(unknown_keys(&serde_yaml::from_str("openAi:\n  model: gpt-4\n").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_wrong_enum_value_and_type() {
+        let problems = validate("defaultRunMode: dryy\n");
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(1));
+        assert!(problems[0].message.contains("expected one of `force`, `ask`, `dry`"), "{}", problems[0].message);
+
+        let problems = validate("examples:\n  maxCount: many\n");
+        assert_eq!(problems[0].line, Some(2));
+        assert!(problems[0].message.contains("invalid type"), "{}", problems[0].message);
+    }
+
+    #[test]
+    fn test_profiles_are_checked_like_the_config() {
+        let text = "profiles:\n  work:\n    directories: [~/work]\n    defaultRunMode: dryy\n  home:\n    openAi:\n      modle: gpt-4o\n";
+
+        let problems = validate(text).into_iter().map(|p| p.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("line 2: profiles.work: unknown variant `dryy`"), "{}", problems[0]);
+        assert_eq!(problems[1], "line 7: unknown key profiles.home.openAi.modle (did you mean model?)");
+    }
+
+    #[test]
+    fn test_old_configs_are_checked_after_the_migration() {
+        assert_eq!(validate("defaultShell: bash\nopenAi:\n  apiKey: null\n"), vec![]);
+        let problems = validate("openAi:\n  apiKey: null\n  modle: gpt-4\n");
+        assert_eq!(problems[0].to_string(), "unknown key openAi.modle (did you mean model?)");
+        let problems = validate("# mine\nversion: 99\n");
+        assert_eq!(problems[0].line, Some(2));
+    }
+
+    #[test]
+    fn test_project_config_cannot_set_endpoint_key_sources_or_interpreters() {
+        let text = "defaultRunMode: force\nopenAi:\n  baseUrl: http://evil.example\n  apiKeyCommand: touch /tmp/pwned\n\
+            interpreters:\n  bash:\n    command: [./evil]\nprofiles:\n  x:\n    openAi:\n      apiKeyFile: key\n\
+            http:\n  maxRetries: 1\npricing: {}\ndefaultShell: ./tools/sh\n";
+
+        let problems = validate_project(text).into_iter().map(|p| p.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(problems, vec![
+            "line 1: defaultRunMode: force cannot be set in a project config, only in the user or system config",
+            "line 2: openAi cannot be set in a project config, only in the user or system config",
+            "line 5: interpreters cannot be set in a project config, only in the user or system config",
+            "line 8: profiles cannot be set in a project config, only in the user or system config",
+            "line 12: http cannot be set in a project config, only in the user or system config",
+            "line 14: pricing cannot be set in a project config, only in the user or system config",
+            "line 15: defaultShell must be the name of a shell in a project config, not a path",
+        ]);
+        assert_eq!(validate(text), vec![]);
+        assert_eq!(validate_project("defaultRunMode: dry\ndefaultShell: zsh\nexamples:\n  maxCount: 1\n"), vec![]);
+    }
+
+    #[test]
+    fn test_valid_config_has_no_problems() {
+        let text = "defaultRunMode: dry\npricing:\n  gpt-4.1:\n    promptPerMillion: 2\n    completionPerMillion: 8\n";
+        assert_eq!(validate(text), vec![]);
+    }
+
+    #[test]
+    fn test_example_config_is_valid() {
+        assert_eq!(validate(include_str!("../example-config.yml")), vec![]);
+    }
+
+    #[test]
+    fn test_get_set_unset() {
+        let mut value: Value = serde_yaml::from_str("pricing:\n  gpt-4.1:\n    promptPerMillion: 2\n").unwrap();
+
+        let price = key_path(&value, "pricing.gpt-4.1.promptPerMillion");
+        assert_eq!(price, vec!["pricing", "gpt-4.1", "promptPerMillion"]);
+        assert_eq!(get(&value, "pricing.gpt-4.1.promptPerMillion"), Some(&Value::Number(2.into())));
+        let model = key_path(&value, "openAi.model");
+        set(&mut value, &model, Value::String("gpt-4o".to_string()));
+        assert_eq!(get(&value, "openAi.model"), Some(&Value::String("gpt-4o".to_string())));
+
+        assert_eq!(schema_key_path("pricing.o3.1.promptPerMillion").unwrap(), vec!["pricing", "o3.1", "promptPerMillion"]);
+        assert_eq!(schema_key_path("openAi.modle"), None);
+        assert_eq!(schema_key_path("profiles.work.openAi.modle"), None);
+
+        assert!(unset(&mut value, &price));
+        assert!(!unset(&mut value, &price));
+        assert_eq!(serde_yaml::to_string(&value).unwrap(), "openAi:\n  model: gpt-4o\n");
+    }
+
+    #[test]
+    fn test_check_key() {
+        let path = |key: &str| key.split('.').map(|s| s.to_string()).collect::<Vec<String>>();
+        assert_eq!(check_key(&path("openAi.model")), None);
+        assert_eq!(check_key(&path("defaultRunMod")), Some("unknown key defaultRunMod (did you mean defaultRunMode?)".to_string()));
+    }
+}
@@ -1,9 +1,29 @@
 use std::io;
 use crate::config::get_houston_dir;
+use crate::interpreter::Interpreter;
+use crate::provider::ProviderRegistry;
 use crate::runner::SimpleScriptRunner;
 use crate::template::{DefaultTemplateEvaluator, TemplateEvaluator};
 
 
+/// Priority of the requirements Houston adds on its own (e.g. the operating system).
+pub const PRIORITY_BUILTIN: u8 = 100;
+/// Priority of input passed to houston directly (e.g. via stdin).
+pub const PRIORITY_INPUT: u8 = 75;
+/// Priority of context files enabled via `-c`.
+pub const PRIORITY_NAMED: u8 = 50;
+/// Priority of the default context file.
+pub const PRIORITY_DEFAULT: u8 = 10;
+
+/// An evaluated piece of context that ends up as a requirement in the prompt.
+/// When the prompt gets too large, blocks with a lower priority are shortened first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextBlock {
+    pub name: String,
+    pub content: String,
+    pub priority: u8,
+}
+
 #[derive(Debug)]
 pub struct ContextCall {
     pub name: String,
@@ -37,19 +57,22 @@ impl ContextCall {
 }
 
 pub fn does_default_ctxt_exist() -> bool {
-    get_context_path_by_name("default").exists()
+    does_ctxt_exist("default")
+}
+
+pub fn does_ctxt_exist(name: &str) -> bool {
+    get_context_path_by_name(name).exists()
 }
 
-pub fn read_and_evaluate_context_file_by_name(name: &str, shell: &str,
-                                              args: &[&str],
+pub fn read_and_evaluate_context_file_by_name(name: &str, shell: &Interpreter,
+                                              args: &[&str], providers: ProviderRegistry,
 ) -> io::Result<String> {
     let template = read_context_file_by_name(name)?;
-    let runner = SimpleScriptRunner::new(shell);
-    let template_evaluator = DefaultTemplateEvaluator::new(Box::new(runner));
+    let runner = SimpleScriptRunner::with_interpreter(shell.clone());
+    let template_evaluator = DefaultTemplateEvaluator::new(Box::new(runner)).with_providers(providers);
     let evaluated = template_evaluator.evaluate(&template, args,
     ).map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::Other,
+        io::Error::other(
             format!("Failed to evaluate context template: {}", e),
         )
     })?;
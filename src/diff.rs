@@ -0,0 +1,81 @@
+use colored::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Computes a line based diff between `old` and `new` using the longest common subsequence.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old = old.lines().collect::<Vec<&str>>();
+    let new = new.lines().collect::<Vec<&str>>();
+
+    // lcs[i][j] = length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            result.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            result.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            result.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    result.extend(old[i..].iter().map(|l| DiffLine::Removed(l)));
+    result.extend(new[j..].iter().map(|l| DiffLine::Added(l)));
+    result
+}
+
+/// Renders a diff in unified style with colored `-` and `+` lines.
+pub fn format_diff(diff: &[DiffLine]) -> String {
+    diff.iter()
+        .map(|line| match line {
+            DiffLine::Same(l) => format!("  {}", l),
+            DiffLine::Removed(l) => format!("- {}", l).red().to_string(),
+            DiffLine::Added(l) => format!("+ {}", l).green().to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::DiffLine::*;
+
+    #[test]
+    fn test_diff_identical() {
+        assert_eq!(diff_lines("a\nb", "a\nb"), vec![Same("a"), Same("b")]);
+    }
+
+    #[test]
+    fn test_diff_changed_line() {
+        let diff = diff_lines("a\nb\nc", "a\nx\nc");
+        assert_eq!(diff, vec![Same("a"), Removed("b"), Added("x"), Same("c")]);
+    }
+
+    #[test]
+    fn test_diff_added_and_removed_at_end() {
+        assert_eq!(diff_lines("a", "a\nb"), vec![Same("a"), Added("b")]);
+        assert_eq!(diff_lines("a\nb", "a"), vec![Same("a"), Removed("b")]);
+        assert_eq!(diff_lines("", "a"), vec![Added("a")]);
+    }
+}
@@ -0,0 +1,245 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{EnvironmentConfig, get_houston_dir};
+use crate::prompt::detect_shell_version;
+use crate::provider::{distribution, system_package_managers};
+use crate::usage::now;
+
+const CACHE_FILE_NAME: &str = "environment.json";
+
+/// Facts about the machine that decide which commands a script can use,
+/// e.g. `apt` or `dnf`, GNU or BSD `sed`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Environment {
+    pub distribution: Option<String>,
+    /// The version of the interpreter that runs the script (e.g. `GNU bash, version 3.2.57`),
+    /// which is not necessarily the user's login shell.
+    pub shell: Option<String>,
+    /// GNU, BSD, BusyBox or uutils.
+    pub coreutils: Option<String>,
+    pub package_managers: Vec<String>,
+    /// docker, podman, kubernetes or lxc.
+    pub container: Option<String>,
+    pub wsl: bool,
+    /// Not cached, since it depends on the session.
+    #[serde(skip)]
+    pub ssh: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedEnvironment {
+    detected_at: u64,
+    /// The interpreter the environment was detected for. A different one invalidates the cache.
+    interpreter: String,
+    environment: Environment,
+}
+
+impl Environment {
+    /// Detects the environment for scripts run by `interpreter` (a program name or path).
+    pub fn detect(interpreter: &str) -> Self {
+        Environment {
+            distribution: distribution(),
+            shell: Some(detect_shell_version(interpreter).unwrap_or_else(|| interpreter.to_string())),
+            coreutils: coreutils_flavour(),
+            package_managers: system_package_managers().iter().map(|m| m.to_string()).collect(),
+            container: container(),
+            wsl: wsl(),
+            ssh: ssh(),
+        }
+    }
+
+    /// The cached environment if it is younger than `ttl` seconds and was detected for the same interpreter,
+    /// otherwise a freshly detected (and cached) one.
+    pub fn load_or_detect(cache_path: &Path, ttl: u64, interpreter: &str) -> Self {
+        Self::load_or_detect_with(cache_path, ttl, interpreter, || Environment::detect(interpreter))
+    }
+
+    fn load_or_detect_with(cache_path: &Path, ttl: u64, interpreter: &str, detect: impl FnOnce() -> Self) -> Self {
+        let cached = std::fs::read_to_string(cache_path).ok()
+            .and_then(|content| serde_json::from_str::<CachedEnvironment>(&content).ok())
+            .filter(|c| c.interpreter == interpreter && now().saturating_sub(c.detected_at) < ttl);
+        if let Some(cached) = cached {
+            return Environment { ssh: ssh(), ..cached.environment };
+        }
+
+        let environment = detect();
+        // a missing cache only makes the next call slower
+        let _ = write_cache(cache_path, &CachedEnvironment {
+            detected_at: now(),
+            interpreter: interpreter.to_string(),
+            environment: environment.clone(),
+        });
+        environment
+    }
+
+    /// The distribution, if it is enabled in the config. It goes into the line about the operating system.
+    pub fn distribution<'a>(&'a self, config: &EnvironmentConfig) -> Option<&'a str> {
+        self.distribution.as_deref().filter(|_| config.distribution)
+    }
+
+    /// The environment as a list of requirements, leaving out the distribution (see [Environment::distribution])
+    /// and the items that are disabled in the config.
+    pub fn describe(&self, config: &EnvironmentConfig) -> String {
+        let mut lines = Vec::new();
+        if let Some(shell) = self.shell.as_ref().filter(|_| config.shell) {
+            lines.push(format!("the script is run by {}", shell));
+        }
+        if let Some(coreutils) = self.coreutils.as_ref().filter(|_| config.coreutils) {
+            lines.push(format!("the core utilities (ls, sed, grep, ...) are the {} versions", coreutils));
+        }
+        if config.package_managers && !self.package_managers.is_empty() {
+            lines.push(format!("the available package managers are: {}", self.package_managers.join(", ")));
+        }
+        if let Some(container) = self.container.as_ref().filter(|_| config.container) {
+            lines.push(format!("the script runs inside a {} container", container));
+        }
+        if self.wsl && config.wsl {
+            lines.push("the script runs in WSL (Windows Subsystem for Linux)".to_string());
+        }
+        if self.ssh && config.ssh {
+            lines.push("the user is connected via SSH".to_string());
+        }
+        lines.iter().map(|l| format!("- {}", l)).collect::<Vec<String>>().join("\n")
+    }
+}
+
+pub fn default_cache_path() -> PathBuf {
+    get_houston_dir().join(CACHE_FILE_NAME)
+}
+
+fn write_cache(path: &Path, cached: &CachedEnvironment) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(cached)?)
+}
+
+fn coreutils_flavour() -> Option<String> {
+    if cfg!(windows) {
+        return None;
+    }
+    let output = Command::new("ls").arg("--version").output().ok()?;
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    parse_coreutils_flavour(&text, std::env::consts::OS).map(|f| f.to_string())
+}
+
+/// Tells the flavour from the output of `ls --version`, which BSD `ls` does not support.
+fn parse_coreutils_flavour(ls_version: &str, os: &str) -> Option<&'static str> {
+    if ls_version.contains("GNU coreutils") {
+        Some("GNU")
+    } else if ls_version.contains("BusyBox") {
+        Some("BusyBox")
+    } else if ls_version.contains("uutils") {
+        Some("uutils")
+    } else if matches!(os, "macos" | "freebsd" | "openbsd" | "netbsd") {
+        Some("BSD")
+    } else {
+        None
+    }
+}
+
+fn container() -> Option<String> {
+    if std::env::var_os("KUBERNETES_SERVICE_HOST").is_some() {
+        return Some("kubernetes".to_string());
+    }
+    if Path::new("/run/.containerenv").exists() {
+        return Some("podman".to_string());
+    }
+    if Path::new("/.dockerenv").exists() {
+        return Some("docker".to_string());
+    }
+    let cgroup = std::fs::read_to_string("/proc/1/cgroup").unwrap_or_default();
+    parse_cgroup_container(&cgroup).map(|c| c.to_string())
+}
+
+fn parse_cgroup_container(cgroup: &str) -> Option<&'static str> {
+    if cgroup.contains("kubepods") {
+        Some("kubernetes")
+    } else if cgroup.contains("docker") {
+        Some("docker")
+    } else if cgroup.contains("lxc") {
+        Some("lxc")
+    } else {
+        None
+    }
+}
+
+fn wsl() -> bool {
+    std::env::var_os("WSL_DISTRO_NAME").is_some() || std::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .is_ok_and(|release| release.to_lowercase().contains("microsoft"))
+}
+
+fn ssh() -> bool {
+    std::env::var_os("SSH_CONNECTION").is_some() || std::env::var_os("SSH_TTY").is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn environment() -> Environment {
+        Environment {
+            distribution: Some("Alpine Linux 3.19.0".to_string()),
+            shell: None,
+            coreutils: Some("BusyBox".to_string()),
+            package_managers: vec!["apk".to_string()],
+            container: Some("docker".to_string()),
+            wsl: false,
+            ssh: true,
+        }
+    }
+
+    #[test]
+    fn test_describe_leaves_out_disabled_items() {
+        let config = EnvironmentConfig { container: false, ssh: false, ..Default::default() };
+
+        assert_eq!(environment().describe(&config), "- the core utilities (ls, sed, grep, ...) are the BusyBox versions\n\
+            - the available package managers are: apk");
+    }
+
+    #[test]
+    fn test_fresh_cache_is_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CACHE_FILE_NAME);
+        write_cache(&path, &CachedEnvironment {
+            detected_at: now(),
+            interpreter: "bash".to_string(),
+            environment: environment(),
+        }).unwrap();
+
+        let detect = || Environment { distribution: Some("Debian GNU/Linux 12".to_string()), ..environment() };
+
+        let loaded = Environment::load_or_detect_with(&path, 60, "bash", || panic!("the cache is fresh"));
+        assert_eq!(loaded.distribution, environment().distribution);
+
+        // another interpreter: detected again
+        let detected = Environment::load_or_detect_with(&path, 60, "zsh", detect);
+        assert_eq!(detected.distribution.as_deref(), Some("Debian GNU/Linux 12"));
+
+        // expired: detected again, and the cache is replaced
+        let detected = Environment::load_or_detect_with(&path, 0, "zsh", detect);
+        assert_eq!(detected.distribution.as_deref(), Some("Debian GNU/Linux 12"));
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("Alpine"));
+    }
+
+    #[test]
+    fn test_parse_coreutils_flavour() {
+        assert_eq!(parse_coreutils_flavour("ls (GNU coreutils) 9.1\n", "linux"), Some("GNU"));
+        assert_eq!(parse_coreutils_flavour("ls: unrecognized option\nBusyBox v1.36.1", "linux"), Some("BusyBox"));
+        assert_eq!(parse_coreutils_flavour("ls: illegal option -- -\n", "macos"), Some("BSD"));
+    }
+
+    #[test]
+    fn test_parse_cgroup_container() {
+        assert_eq!(parse_cgroup_container("0::/kubepods/besteffort/pod1"), Some("kubernetes"));
+        assert_eq!(parse_cgroup_container("12:cpu:/docker/abc"), Some("docker"));
+        assert_eq!(parse_cgroup_container("0::/init.scope"), None);
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{find_project_dir, get_houston_dir};
+use crate::prompt::{canonical_lang, lang_file_stem};
+use crate::tokens::TokenEstimator;
+
+const EXAMPLES_DIR_NAME: &str = "examples";
+
+/// Words that carry no meaning for matching instructions against each other.
+const STOP_WORDS: [&str; 32] = [
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "into", "is", "it", "me",
+    "my", "of", "on", "or", "please", "that", "the", "this", "to", "with", "all", "i", "you", "can",
+    "want", "do", "we",
+];
+
+/// A curated instruction -> script pair that shows the model the preferred style.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Example {
+    pub instruction: String,
+    pub script: String,
+    /// Extra words that should match this example, in addition to the words of the instruction.
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+/// Loads the examples for the given language from the houston dir and the project `.houston` dir.
+/// Files are named `<lang>.yml`, e.g. `examples/bash.yml`.
+pub fn load_examples(lang: &str) -> io::Result<Vec<Example>> {
+    let mut dirs = vec![get_houston_dir().join(EXAMPLES_DIR_NAME)];
+    if let Some(project_dir) = find_project_dir() {
+        dirs.push(project_dir.join(EXAMPLES_DIR_NAME));
+    }
+    load_examples_from(&dirs, lang)
+}
+
+pub fn load_examples_from(dirs: &[PathBuf], lang: &str) -> io::Result<Vec<Example>> {
+    let stem = lang_file_stem(lang);
+    let canonical = canonical_lang(&stem);
+    let mut names = vec![stem.as_str()];
+    if canonical != stem {
+        names.push(canonical);
+    }
+
+    let mut examples = Vec::new();
+    for dir in dirs {
+        for name in &names {
+            examples.extend(read_examples_file(&dir.join(format!("{}.yml", name)))?);
+        }
+    }
+    Ok(examples)
+}
+
+fn read_examples_file(path: &Path) -> io::Result<Vec<Example>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let file = std::fs::File::open(path)?;
+    serde_yaml::from_reader(file).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+    })
+}
+
+fn keywords(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(|w| w.to_lowercase())
+        .filter(|w| !w.is_empty() && !STOP_WORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// Share of the instruction's keywords that also appear in the example (0 to 1).
+fn similarity(instruction_keywords: &HashSet<String>, example: &Example) -> f64 {
+    if instruction_keywords.is_empty() {
+        return 0.0;
+    }
+    let mut example_keywords = keywords(&example.instruction);
+    example_keywords.extend(example.keywords.iter().map(|k| k.to_lowercase()));
+
+    let common = instruction_keywords.intersection(&example_keywords).count();
+    common as f64 / instruction_keywords.len() as f64
+}
+
+/// Picks the examples that are most similar to the instruction.
+/// Examples without any keyword in common are never selected.
+/// At most `max_count` examples are returned, using at most `max_tokens` tokens in total.
+pub fn select_examples(examples: &[Example], instruction: &str, max_count: usize, max_tokens: usize,
+                       estimator: &TokenEstimator) -> Vec<Example> {
+    let instruction_keywords = keywords(instruction);
+
+    let mut scored = examples.iter()
+        .map(|e| (similarity(&instruction_keywords, e), e))
+        .filter(|(score, _)| *score > 0.0)
+        .collect::<Vec<(f64, &Example)>>();
+    // stable sort keeps the file order for equal scores
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut selected = Vec::new();
+    let mut tokens = 0;
+    for (_, example) in scored {
+        if selected.len() >= max_count {
+            break;
+        }
+        let example_tokens = estimator.count(&example.instruction) + estimator.count(&example.script);
+        if tokens + example_tokens > max_tokens {
+            continue;
+        }
+        tokens += example_tokens;
+        selected.push(example.clone());
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example(instruction: &str, script: &str) -> Example {
+        Example {
+            instruction: instruction.to_string(),
+            script: script.to_string(),
+            keywords: vec![],
+        }
+    }
+
+    #[test]
+    fn test_load_examples_from_multiple_dirs() {
+        let user_dir = tempfile::tempdir().unwrap();
+        let project_dir = tempfile::tempdir().unwrap();
+        std::fs::write(user_dir.path().join("python.yml"),
+                       "- instruction: say hi\n  script: print('hi')\n").unwrap();
+        std::fs::write(project_dir.path().join("python3.yml"),
+                       "- instruction: list files\n  script: import os\n  keywords: [ls]\n").unwrap();
+
+        let dirs = vec![user_dir.path().to_path_buf(), project_dir.path().to_path_buf()];
+        let examples = load_examples_from(&dirs, "python3").unwrap();
+
+        assert_eq!(examples.len(), 2);
+        assert_eq!(examples[0].instruction, "say hi");
+        assert_eq!(examples[1].keywords, vec!["ls"]);
+    }
+
+    #[test]
+    fn test_load_examples_reports_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("bash.yml"), "- foo: bar\n").unwrap();
+
+        let result = load_examples_from(&[dir.path().to_path_buf()], "bash");
+
+        assert!(result.unwrap_err().to_string().contains("bash.yml"));
+    }
+
+    #[test]
+    fn test_select_examples_by_similarity() {
+        let estimator = TokenEstimator::for_model("gpt-4");
+        let mut tagged = example("show running things", "docker ps");
+        tagged.keywords = vec!["Docker".to_string()];
+        let examples = vec![
+            example("find large files", "find . -size +100M"),
+            example("remove all stopped docker containers", "docker container prune -f"),
+            tagged,
+            example("print the weather", "curl wttr.in"),
+        ];
+
+        let selected = select_examples(&examples, "list all docker containers", 2, 1000, &estimator);
+
+        assert_eq!(selected.len(), 2);
+        assert_eq!(selected[0].script, "docker container prune -f");
+        assert_eq!(selected[1].script, "docker ps");
+    }
+
+    #[test]
+    fn test_select_examples_respects_token_budget() {
+        let estimator = TokenEstimator::for_model("gpt-4");
+        let examples = vec![
+            example("list docker containers", &"docker ps ".repeat(100)),
+            example("list docker images", "docker images"),
+        ];
+
+        let selected = select_examples(&examples, "list docker stuff", 5, 20, &estimator);
+
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].script, "docker images");
+    }
+}
@@ -1,43 +1,137 @@
 use std::fmt::{Display, Formatter};
-use crate::config::ApiKey;
-use openai_api_rust::*;
+use crate::config::{ApiKey, SamplingParameters};
+use crate::examples::Example;
+use crate::http::{HttpClient, HttpError};
+use crate::prompt::{PromptTemplate, PromptVariables};
+use crate::usage::TokenUsage;
+use openai_api_rust::{Message, Role};
 use openai_api_rust::chat::*;
+use openai_api_rust::completions::Completion;
 
+#[derive(Debug, Clone)]
 pub struct ScriptSpecification {
     pub lang: String,
     pub instruction: String,
     pub requirements: Vec<String>,
+    /// Example instruction/script pairs that show the expected style.
+    pub examples: Vec<Example>,
+    /// Earlier instructions of the same session and their scripts, oldest first.
+    pub history: Vec<Turn>,
+    /// Earlier scripts for this instruction and what was wrong with them, oldest first.
+    pub repairs: Vec<Repair>,
+}
+
+/// An instruction of an interactive session and the script that was generated for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    pub instruction: String,
+    pub script: String,
+}
+
+/// A generated script that turned out to be broken, e.g. because it failed the syntax check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repair {
+    pub script: String,
+    /// What went wrong, e.g. the output of a syntax checker.
+    pub problem: String,
+}
+
+impl Repair {
+    fn feedback(&self) -> String {
+        format!("The script has a problem:\n{}\n\nReply with the complete corrected script.", self.problem)
+    }
+}
+
+/// The result of a generator call: the (de-duplicated) candidate scripts and the tokens they cost.
+#[derive(Debug)]
+pub struct Generation {
+    pub scripts: Vec<String>,
+    pub usage: TokenUsage,
 }
 
 pub trait ScriptGenerator {
-    fn generate(&self, spec: &ScriptSpecification) -> String;
+    fn generate(&self, spec: &ScriptSpecification) -> Result<Generation, GeneratorError>;
+}
+
+#[derive(Debug)]
+pub enum GeneratorError {
+    Http(HttpError),
+    InvalidResponse(String),
+}
+
+impl Display for GeneratorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeneratorError::Http(e) => write!(f, "{}", e),
+            GeneratorError::InvalidResponse(msg) => write!(f, "Invalid response from the API: {}", msg),
+        }
+    }
+}
+
+impl From<HttpError> for GeneratorError {
+    fn from(e: HttpError) -> Self {
+        GeneratorError::Http(e)
+    }
 }
 
 
 #[derive(Debug)]
 pub struct ChatPrompt {
     system_message: String,
+    /// Few-shot examples, then the earlier turns of the session, as (user message, assistant message) pairs.
+    examples: Vec<(String, String)>,
     user_message: String,
+    /// Follow-up turns as (assistant message, user message) pairs, asking to repair a script.
+    follow_ups: Vec<(String, String)>,
 }
 
 impl Display for ChatPrompt {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[System]\n{}\n[User]\n{}", self.system_message, self.user_message)
+        write!(f, "[System]\n{}\n", self.system_message)?;
+        for (user, assistant) in &self.examples {
+            write!(f, "[User]\n{}\n[Assistant]\n{}\n", user, assistant)?;
+        }
+        write!(f, "[User]\n{}", self.user_message)?;
+        for (assistant, user) in &self.follow_ups {
+            write!(f, "\n[Assistant]\n{}\n[User]\n{}", assistant, user)?;
+        }
+        Ok(())
     }
 }
 
 impl ChatPrompt {
     fn to_messages(&self) -> Vec<Message> {
-        vec![
+        let mut messages = vec![
             Message {
                 role: Role::System,
                 content: self.system_message.clone(),
             },
-            Message {
+        ];
+        for (user, assistant) in &self.examples {
+            messages.push(Message {
                 role: Role::User,
-                content: self.user_message.clone(),
-            },
-        ]
+                content: user.clone(),
+            });
+            messages.push(Message {
+                role: Role::Assistant,
+                content: assistant.clone(),
+            });
+        }
+        messages.push(Message {
+            role: Role::User,
+            content: self.user_message.clone(),
+        });
+        for (assistant, user) in &self.follow_ups {
+            messages.push(Message {
+                role: Role::Assistant,
+                content: assistant.clone(),
+            });
+            messages.push(Message {
+                role: Role::User,
+                content: user.clone(),
+            });
+        }
+        messages
     }
 }
 
@@ -45,73 +139,236 @@ impl ChatPrompt {
 pub struct ChatGptScriptGenerator {
     api_key: ApiKey,
     model: String,
+    base_url: String,
+    http_client: HttpClient,
+    sampling: SamplingParameters,
+    candidates: u32,
+    prompt_template: PromptTemplate,
 }
 
 impl ChatGptScriptGenerator {
-    pub fn new(api_key: ApiKey, model: String) -> Self {
+    pub fn new(api_key: ApiKey, model: String, base_url: String, http_client: HttpClient,
+               sampling: SamplingParameters, candidates: u32, prompt_template: PromptTemplate) -> Self {
         ChatGptScriptGenerator {
             api_key,
             model,
+            base_url,
+            http_client,
+            sampling,
+            candidates: candidates.max(1),
+            prompt_template,
         }
     }
+
+    fn chat_completions_url(&self) -> String {
+        format!("{}/chat/completions", self.base_url.trim_end_matches('/'))
+    }
 }
 
 impl ScriptGenerator for ChatGptScriptGenerator {
-    fn generate(&self, spec: &ScriptSpecification) -> String {
-        let api_key = &self.api_key.0;
-
-        let auth = Auth::new(api_key);
-        let openai = OpenAI::new(auth, "https://api.openai.com/v1/");
-
-        let prompt = create_chat_prompt(spec);
+    fn generate(&self, spec: &ScriptSpecification) -> Result<Generation, GeneratorError> {
+        let prompt = create_chat_prompt(spec, &self.prompt_template);
 
         let body = ChatBody {
             model: self.model.clone(),
             messages: prompt.to_messages(),
-            temperature: Some(0.5),
-            top_p: Some(1.0),
-            n: Some(1),
+            temperature: self.sampling.temperature,
+            top_p: self.sampling.top_p,
+            n: Some(self.candidates as i32),
             stream: None,
-            stop: None,
-            max_tokens: None,
-            presence_penalty: None,
-            frequency_penalty: None,
+            stop: self.sampling.stop.clone(),
+            max_tokens: self.sampling.max_tokens.map(|t| t as i32),
+            presence_penalty: self.sampling.presence_penalty,
+            frequency_penalty: self.sampling.frequency_penalty,
             logit_bias: None,
             user: None,
         };
 
-        let response = openai.chat_completion_create(&body);
-        let completion = response.unwrap();
+        let mut body = serde_json::to_value(&body).expect("chat body is always serializable");
+        // ChatBody predates the seed parameter
+        if let Some(seed) = self.sampling.seed {
+            body["seed"] = serde_json::json!(seed);
+        }
+        let authorization = format!("Bearer {}", self.api_key.0);
+        let response = self.http_client.post_json(
+            &self.chat_completions_url(),
+            &[("Authorization", &authorization)],
+            &body,
+        ).map_err(|e| e.map_message(|message| self.api_key.hide_in(message)))?;
+
+        let completion: Completion = serde_json::from_value(response)
+            .map_err(|e| GeneratorError::InvalidResponse(e.to_string()))?;
+
+        let scripts = dedup_scripts(completion.choices.iter()
+            .filter_map(|choice| choice.message.as_ref())
+            .map(|message| message.content.trim().to_string()));
+
+        if scripts.is_empty() {
+            return Err(GeneratorError::InvalidResponse("no choices returned".to_string()));
+        }
+
+        let usage = TokenUsage {
+            prompt_tokens: completion.usage.prompt_tokens.unwrap_or(0) as u64,
+            completion_tokens: completion.usage.completion_tokens.unwrap_or(0) as u64,
+        };
 
-        let choice = completion.choices.first().unwrap();
-        choice.message.as_ref().unwrap().content.clone().trim().to_string()
+        Ok(Generation { scripts, usage })
     }
 }
 
-pub fn create_chat_prompt(spec: &ScriptSpecification) -> ChatPrompt {
-    let lang = &spec.lang;
-    let system_message = format!("You are a {0} script generator.\n\
-    The user gives you a description/goal for a {0} script and sometimes a list of extra requirements or context.\n\
-    You respond with the {0} script.\nNote that you only respond with the script, not additional explanation, no code block etc.\n\
-    Your response must be a valid {0} script. So never use ''' to indicate the script start and end", lang);
+/// Removes scripts that only differ in trailing whitespace, keeping the first occurrence.
+fn dedup_scripts(scripts: impl Iterator<Item=String>) -> Vec<String> {
+    fn normalize(script: &str) -> String {
+        script.lines().map(|l| l.trim_end()).collect::<Vec<&str>>().join("\n")
+    }
+
+    let mut seen = Vec::new();
+    let mut unique = Vec::new();
+    for script in scripts {
+        let normalized = normalize(&script);
+        if !seen.contains(&normalized) {
+            seen.push(normalized);
+            unique.push(script);
+        }
+    }
+    unique
+}
+
+pub fn create_chat_prompt(spec: &ScriptSpecification, template: &PromptTemplate) -> ChatPrompt {
+    let requirements = format_requirements(&spec.requirements);
+
+    let system_message = template.render(&PromptVariables {
+        lang: &spec.lang,
+        os: std::env::consts::OS,
+        requirements: &requirements,
+    });
 
     let mut user_message = spec.instruction.clone();
 
-    if !spec.requirements.is_empty() {
+    if !spec.requirements.is_empty() && !template.uses_requirements() {
         user_message.push_str("\n\nAdditional Requirements:\n");
-        for requirement in &spec.requirements {
-            if !requirement.is_empty() {
-                user_message.push('\n');
-                if !requirement.starts_with('-') {
-                    user_message.push_str("- ")
-                }
-                user_message.push_str(requirement);
-            }
-        }
+        user_message.push_str(&requirements);
     }
 
     ChatPrompt {
         system_message,
+        examples: spec.examples.iter().map(|e| (e.instruction.clone(), e.script.clone()))
+            .chain(spec.history.iter().map(|t| (t.instruction.clone(), t.script.clone())))
+            .collect(),
         user_message,
+        follow_ups: spec.repairs.iter().map(|r| (r.script.clone(), r.feedback())).collect(),
     }
-}
\ No newline at end of file
+}
+
+/// Formats the requirements as a bullet list (each line starting with a newline).
+fn format_requirements(requirements: &[String]) -> String {
+    let mut result = String::new();
+    for requirement in requirements {
+        if !requirement.is_empty() {
+            result.push('\n');
+            if !requirement.starts_with('-') {
+                result.push_str("- ")
+            }
+            result.push_str(requirement);
+        }
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::prompt::PromptTemplateSource;
+    use super::*;
+
+    fn spec(requirements: Vec<&str>) -> ScriptSpecification {
+        ScriptSpecification {
+            lang: "fish".to_string(),
+            instruction: "say hello".to_string(),
+            requirements: requirements.into_iter().map(String::from).collect(),
+            examples: vec![],
+            history: vec![],
+            repairs: vec![],
+        }
+    }
+
+    fn template(text: &str) -> PromptTemplate {
+        PromptTemplate::new(PromptTemplateSource::BuiltIn("test"), text.to_string())
+    }
+
+    #[test]
+    fn test_requirements_are_appended_to_user_message() {
+        let prompt = create_chat_prompt(&spec(vec!["be nice", "- be fast", ""]), &template("{{lang}} generator"));
+
+        assert_eq!(prompt.system_message, "fish generator");
+        assert_eq!(prompt.user_message, "say hello\n\nAdditional Requirements:\n\n- be nice\n- be fast");
+    }
+
+    #[test]
+    fn test_requirements_placeholder_moves_requirements_to_system_message() {
+        let prompt = create_chat_prompt(&spec(vec!["be nice"]), &template("rules:{{requirements}}"));
+
+        assert_eq!(prompt.system_message, "rules:\n- be nice");
+        assert_eq!(prompt.user_message, "say hello");
+    }
+
+    #[test]
+    fn test_examples_become_prior_turns() {
+        let mut spec = spec(vec![]);
+        spec.examples = vec![Example {
+            instruction: "say bye".to_string(),
+            script: "echo bye".to_string(),
+            keywords: vec![],
+        }];
+
+        let messages = create_chat_prompt(&spec, &template("system")).to_messages();
+
+        let roles = messages.iter().map(|m| format!("{:?}", m.role)).collect::<Vec<String>>();
+        assert_eq!(roles, vec!["System", "User", "Assistant", "User"]);
+        assert_eq!(messages[1].content, "say bye");
+        assert_eq!(messages[2].content, "echo bye");
+        assert_eq!(messages[3].content, "say hello");
+    }
+
+    #[test]
+    fn test_history_follows_the_examples() {
+        let mut spec = spec(vec![]);
+        spec.examples = vec![Example {
+            instruction: "say bye".to_string(),
+            script: "echo bye".to_string(),
+            keywords: vec![],
+        }];
+        spec.history = vec![Turn {
+            instruction: "list the files".to_string(),
+            script: "ls".to_string(),
+        }];
+
+        let messages = create_chat_prompt(&spec, &template("system")).to_messages();
+
+        let contents = messages.iter().skip(1).map(|m| m.content.as_str()).collect::<Vec<&str>>();
+        assert_eq!(contents, vec!["say bye", "echo bye", "list the files", "ls", "say hello"]);
+    }
+
+    #[test]
+    fn test_repairs_become_follow_up_turns() {
+        let mut spec = spec(vec![]);
+        spec.repairs = vec![Repair {
+            script: "echo \"hello".to_string(),
+            problem: "unexpected EOF".to_string(),
+        }];
+
+        let messages = create_chat_prompt(&spec, &template("system")).to_messages();
+
+        let roles = messages.iter().map(|m| format!("{:?}", m.role)).collect::<Vec<String>>();
+        assert_eq!(roles, vec!["System", "User", "Assistant", "User"]);
+        assert_eq!(messages[1].content, "say hello");
+        assert_eq!(messages[2].content, "echo \"hello");
+        assert!(messages[3].content.contains("unexpected EOF"));
+    }
+
+    #[test]
+    fn test_dedup_scripts() {
+        let scripts = vec!["echo a", "echo b", "echo a  ", "echo b\n"].into_iter().map(String::from);
+        assert_eq!(dedup_scripts(scripts), vec!["echo a", "echo b"]);
+    }
+}
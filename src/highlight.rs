@@ -0,0 +1,202 @@
+use colored::*;
+
+use crate::prompt::{canonical_lang, lang_file_stem};
+
+const SHELL_KEYWORDS: [&str; 20] = [
+    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac", "in",
+    "function", "return", "local", "export", "set", "exit", "end",
+];
+const POWERSHELL_KEYWORDS: [&str; 16] = [
+    "if", "else", "elseif", "foreach", "for", "while", "do", "switch", "function", "param", "return",
+    "try", "catch", "finally", "throw", "in",
+];
+const PYTHON_KEYWORDS: [&str; 25] = [
+    "def", "class", "if", "elif", "else", "for", "while", "in", "return", "import", "from", "as", "with",
+    "try", "except", "finally", "raise", "not", "and", "or", "is", "None", "True", "False", "lambda",
+];
+const JS_KEYWORDS: [&str; 20] = [
+    "function", "const", "let", "var", "if", "else", "for", "while", "return", "import", "from", "export",
+    "async", "await", "new", "try", "catch", "throw", "of", "in",
+];
+const RUBY_KEYWORDS: [&str; 16] = [
+    "def", "class", "module", "if", "elsif", "else", "unless", "end", "do", "while", "each", "return",
+    "require", "begin", "rescue", "nil",
+];
+
+/// Lexical rules of a script language, just enough for highlighting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Syntax {
+    line_comment: &'static str,
+    /// Character that starts a variable, e.g. `$` in shells.
+    variable_prefix: Option<char>,
+    keywords: &'static [&'static str],
+}
+
+impl Syntax {
+    /// The syntax for a shell or language name (e.g. `bash`, `/usr/bin/python3`, `pwsh`).
+    /// Unknown languages get shell syntax.
+    pub fn for_lang(lang: &str) -> Syntax {
+        let syntax = |line_comment, variable_prefix, keywords| Syntax { line_comment, variable_prefix, keywords };
+        match canonical_lang(&lang_file_stem(lang)) {
+            "powershell" => syntax("#", Some('$'), &POWERSHELL_KEYWORDS[..]),
+            "python" => syntax("#", None, &PYTHON_KEYWORDS[..]),
+            "node" | "deno" | "javascript" | "typescript" => syntax("//", None, &JS_KEYWORDS[..]),
+            "ruby" => syntax("#", Some('@'), &RUBY_KEYWORDS[..]),
+            "cmd" => syntax("REM", Some('%'), &[]),
+            _ => syntax("#", Some('$'), &SHELL_KEYWORDS[..]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Plain,
+    Keyword,
+    String,
+    Variable,
+    Comment,
+}
+
+/// Splits a single line into highlightable pieces.
+/// Strings and comments are recognized within the line only; multiline strings are not supported.
+pub fn tokenize<'a>(line: &'a str, syntax: &Syntax) -> Vec<(TokenKind, &'a str)> {
+    let mut tokens = Vec::new();
+    let mut plain_start = 0;
+    let mut chars = line.char_indices().peekable();
+
+    let push = |tokens: &mut Vec<(TokenKind, &'a str)>, plain_start: &mut usize, start: usize, end: usize, kind| {
+        if *plain_start < start {
+            tokens.push((TokenKind::Plain, &line[*plain_start..start]));
+        }
+        tokens.push((kind, &line[start..end]));
+        *plain_start = end;
+    };
+
+    while let Some((start, c)) = chars.next() {
+        let rest = &line[start..];
+        if rest.starts_with(syntax.line_comment)
+            && line[..start].chars().last().is_none_or(|p| p.is_whitespace()) {
+            push(&mut tokens, &mut plain_start, start, line.len(), TokenKind::Comment);
+            break;
+        }
+
+        if c == '"' || c == '\'' {
+            let mut end = line.len();
+            let mut escaped = false;
+            for (i, next) in chars.by_ref() {
+                if next == c && !escaped {
+                    end = i + next.len_utf8();
+                    break;
+                }
+                escaped = next == '\\' && !escaped;
+            }
+            push(&mut tokens, &mut plain_start, start, end, TokenKind::String);
+            continue;
+        }
+
+        if Some(c) == syntax.variable_prefix {
+            let mut end = start + c.len_utf8();
+            if chars.peek().map(|(_, next)| *next) == Some('{') {
+                // ${...} up to the closing brace
+                end = line.len();
+                for (i, next) in chars.by_ref() {
+                    if next == '}' {
+                        end = i + 1;
+                        break;
+                    }
+                }
+            } else {
+                while let Some((i, next)) = chars.peek() {
+                    if !(next.is_alphanumeric() || *next == '_' || *next == ':') {
+                        break;
+                    }
+                    end = i + next.len_utf8();
+                    chars.next();
+                }
+            }
+            if end > start + c.len_utf8() {
+                push(&mut tokens, &mut plain_start, start, end, TokenKind::Variable);
+            }
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            let word_starts_here = line[..start].chars().last()
+                .is_none_or(|p| !(p.is_alphanumeric() || p == '_' || p == '-'));
+            let mut end = start + c.len_utf8();
+            while let Some((i, next)) = chars.peek() {
+                if !(next.is_alphanumeric() || *next == '_') {
+                    break;
+                }
+                end = i + next.len_utf8();
+                chars.next();
+            }
+            let word_ends_here = !line[end..].starts_with('-');
+            if word_starts_here && word_ends_here && syntax.keywords.contains(&&line[start..end]) {
+                push(&mut tokens, &mut plain_start, start, end, TokenKind::Keyword);
+            }
+        }
+    }
+
+    if plain_start < line.len() {
+        tokens.push((TokenKind::Plain, &line[plain_start..]));
+    }
+    tokens
+}
+
+/// Returns the script with ANSI colors for keywords, strings, variables and comments.
+pub fn highlight(script: &str, syntax: &Syntax) -> String {
+    script.lines()
+        .map(|line| {
+            tokenize(line, syntax).into_iter()
+                .map(|(kind, text)| match kind {
+                    TokenKind::Plain => text.green(),
+                    TokenKind::Keyword => text.magenta().bold(),
+                    TokenKind::String => text.yellow(),
+                    TokenKind::Variable => text.cyan(),
+                    TokenKind::Comment => text.bright_black(),
+                }.to_string())
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::TokenKind::*;
+
+    #[test]
+    fn test_tokenize_shell() {
+        let syntax = Syntax::for_lang("bash");
+        assert_eq!(tokenize("if [ -f \"$f\" ]; then echo $HOME # done", &syntax), vec![
+            (Keyword, "if"), (Plain, " [ -f "), (String, "\"$f\""), (Plain, " ]; "), (Keyword, "then"),
+            (Plain, " echo "), (Variable, "$HOME"), (Plain, " "), (Comment, "# done"),
+        ]);
+    }
+
+    #[test]
+    fn test_tokenize_ignores_keywords_inside_words() {
+        let syntax = Syntax::for_lang("bash");
+        assert_eq!(tokenize("git done-list fifo", &syntax), vec![(Plain, "git done-list fifo")]);
+        assert_eq!(tokenize("echo ${#arr[@]} $#", &syntax), vec![(Plain, "echo "), (Variable, "${#arr[@]}"), (Plain, " $#")]);
+    }
+
+    #[test]
+    fn test_tokenize_python() {
+        let syntax = Syntax::for_lang("/usr/bin/python3");
+        assert_eq!(tokenize("for x in 'a\\'b': # $x", &syntax), vec![
+            (Keyword, "for"), (Plain, " x "), (Keyword, "in"), (Plain, " "), (String, "'a\\'b'"),
+            (Plain, ": "), (Comment, "# $x"),
+        ]);
+    }
+
+    #[test]
+    fn test_tokenize_unterminated_string() {
+        let syntax = Syntax::for_lang("node");
+        assert_eq!(tokenize("const s = \"abc", &syntax), vec![
+            (Keyword, "const"), (Plain, " s = "), (String, "\"abc"),
+        ]);
+    }
+}
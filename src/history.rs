@@ -0,0 +1,148 @@
+use std::io;
+use std::path::PathBuf;
+
+use crate::prompt::{canonical_lang, lang_file_stem};
+
+/// The format of a shell's history file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryFormat {
+    /// One command per line, optionally preceded by `#<timestamp>` lines (bash, PSReadLine).
+    Lines,
+    /// `: <timestamp>:<duration>;<command>` with the extended history option, or plain lines.
+    /// Multi-line commands continue with a trailing backslash.
+    Zsh,
+    /// `- cmd: <command>` entries.
+    Fish,
+}
+
+/// The history file of the given shell and its format, if the shell is known.
+/// `$HISTFILE` is respected for bash and zsh.
+pub fn history_file(shell: &str) -> Option<(PathBuf, HistoryFormat)> {
+    let home = dirs::home_dir()?;
+    let hist_file = std::env::var_os("HISTFILE").map(PathBuf::from);
+    match canonical_lang(&lang_file_stem(shell)) {
+        "bash" | "sh" => Some((hist_file.unwrap_or_else(|| home.join(".bash_history")), HistoryFormat::Lines)),
+        "zsh" => Some((hist_file.unwrap_or_else(|| home.join(".zsh_history")), HistoryFormat::Zsh)),
+        "fish" => Some((dirs::data_dir()?.join("fish").join("fish_history"), HistoryFormat::Fish)),
+        "powershell" => {
+            let dir = if cfg!(windows) {
+                dirs::config_dir()?.join("Microsoft").join("Windows").join("PowerShell")
+            } else {
+                dirs::data_dir()?.join("powershell")
+            };
+            Some((dir.join("PSReadLine").join("ConsoleHost_history.txt"), HistoryFormat::Lines))
+        }
+        _ => None,
+    }
+}
+
+/// Parses the commands of a history file, oldest first.
+pub fn parse_history(content: &str, format: HistoryFormat) -> Vec<String> {
+    match format {
+        HistoryFormat::Lines => content.lines()
+            .filter(|l| !is_timestamp(l))
+            .map(|l| l.to_string())
+            .collect(),
+        HistoryFormat::Zsh => {
+            let mut commands: Vec<String> = Vec::new();
+            let mut continued = false;
+            for line in content.lines() {
+                if continued {
+                    if let Some(last) = commands.last_mut() {
+                        last.push('\n');
+                        last.push_str(line.strip_suffix('\\').unwrap_or(line));
+                    }
+                } else {
+                    let command = match line.strip_prefix(": ").and_then(|l| l.split_once(';')) {
+                        Some((_, command)) => command,
+                        None => line,
+                    };
+                    commands.push(command.strip_suffix('\\').unwrap_or(command).to_string());
+                }
+                continued = line.ends_with('\\');
+            }
+            commands
+        }
+        HistoryFormat::Fish => content.lines()
+            .filter_map(|l| l.strip_prefix("- cmd: "))
+            .map(unescape_fish)
+            .collect(),
+    }
+}
+
+/// fish escapes backslashes and newlines in its history.
+fn unescape_fish(command: &str) -> String {
+    let mut result = String::new();
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                result.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                result.push('\\');
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn is_timestamp(line: &str) -> bool {
+    line.strip_prefix('#').is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// The most recent command in the shell's history that is not a call of houston itself.
+pub fn last_command(shell: &str) -> io::Result<Option<String>> {
+    let (path, format) = match history_file(shell) {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+    if !path.exists() {
+        return Ok(None);
+    }
+    // zsh may write metafied bytes that are not valid UTF-8
+    let content = String::from_utf8_lossy(&std::fs::read(path)?).to_string();
+    Ok(last_foreign_command(&parse_history(&content, format)))
+}
+
+fn last_foreign_command(commands: &[String]) -> Option<String> {
+    commands.iter()
+        .rev()
+        .map(|c| c.trim())
+        .find(|c| !c.is_empty() && c.split_whitespace().next() != Some("hu"))
+        .map(|c| c.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bash_history_with_timestamps() {
+        let content = "#1700000000\nls -la\n#1700000001\ngit psuh\n";
+        assert_eq!(parse_history(content, HistoryFormat::Lines), vec!["ls -la", "git psuh"]);
+    }
+
+    #[test]
+    fn test_parse_zsh_extended_history() {
+        let content = ": 1700000000:0;ls\n: 1700000001:0;for f in *; do\\\necho $f\\\ndone\nplain command\n";
+        assert_eq!(parse_history(content, HistoryFormat::Zsh),
+                   vec!["ls", "for f in *; do\necho $f\ndone", "plain command"]);
+    }
+
+    #[test]
+    fn test_parse_fish_history() {
+        let content = "- cmd: ls\n  when: 1700000000\n- cmd: echo a\\\\nb\\nls\n  when: 1700000001\n";
+        assert_eq!(parse_history(content, HistoryFormat::Fish), vec!["ls", "echo a\\nb\nls"]);
+    }
+
+    #[test]
+    fn test_last_command_skips_houston_calls() {
+        let commands = vec!["tar xf a.tgz".to_string(), "hu fix".to_string(), "  ".to_string()];
+        assert_eq!(last_foreign_command(&commands), Some("tar xf a.tgz".to_string()));
+        assert_eq!(last_foreign_command(&["hu".to_string()]), None);
+    }
+}
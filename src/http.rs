@@ -0,0 +1,378 @@
+use std::fmt::{Display, Formatter};
+use std::thread;
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::config::HttpConfig;
+
+/// Error codes the OpenAI API uses to signal that retrying will not help.
+const QUOTA_ERROR_CODES: [&str; 2] = ["insufficient_quota", "billing_hard_limit_reached"];
+
+#[derive(Debug)]
+pub enum HttpError {
+    /// The server answered with a non-success status code (after all retries).
+    Status { code: u16, message: String },
+    /// The account has no quota left. Retrying will not help.
+    QuotaExhausted(String),
+    /// The request could not be sent or the response could not be read.
+    Transport(String),
+    /// The response body was not valid JSON.
+    InvalidResponse(String),
+}
+
+impl HttpError {
+    /// The same error with its message changed, e.g. to remove secrets from it.
+    pub fn map_message(self, f: impl Fn(&str) -> String) -> Self {
+        match self {
+            HttpError::Status { code, message } => HttpError::Status { code, message: f(&message) },
+            HttpError::QuotaExhausted(message) => HttpError::QuotaExhausted(f(&message)),
+            HttpError::Transport(message) => HttpError::Transport(f(&message)),
+            HttpError::InvalidResponse(message) => HttpError::InvalidResponse(f(&message)),
+        }
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpError::Status { code: 429, message } => {
+                write!(f, "Rate limit exceeded, giving up after retrying: {}", message)
+            }
+            HttpError::Status { code, message } => {
+                write!(f, "Request failed with status {}: {}", code, message)
+            }
+            HttpError::QuotaExhausted(message) => {
+                write!(f, "Your API quota is exhausted. Check your plan and billing details. ({})", message)
+            }
+            HttpError::Transport(message) => {
+                write!(f, "Request failed: {}", message)
+            }
+            HttpError::InvalidResponse(message) => {
+                write!(f, "Invalid response: {}", message)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with full jitter, capped at `max_backoff`.
+    /// A `Retry-After` value sent by the server takes precedence, but is capped as well.
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_backoff);
+        }
+        let exponential = self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        let millis = exponential.as_millis() as u64;
+        if millis == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(millis / 2..=millis))
+    }
+}
+
+/// Blocking JSON-over-HTTP client shared by all generator backends.
+/// Takes care of timeouts and retries transient failures (429, 5xx, connection errors).
+#[derive(Debug)]
+pub struct HttpClient {
+    agent: ureq::Agent,
+    retry_policy: RetryPolicy,
+}
+
+enum AttemptError {
+    Retryable { error: HttpError, retry_after: Option<Duration> },
+    Fatal(HttpError),
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build();
+
+        HttpClient {
+            agent,
+            retry_policy: RetryPolicy {
+                max_retries: config.max_retries,
+                initial_backoff: Duration::from_millis(config.initial_backoff_millis),
+                max_backoff: Duration::from_millis(config.max_backoff_millis),
+            },
+        }
+    }
+
+    pub fn post_json(&self, url: &str, headers: &[(&str, &str)], body: &serde_json::Value)
+                     -> Result<serde_json::Value, HttpError> {
+        let request = || self.agent.post(url).set("Content-Type", "application/json");
+        self.send_with_retries(request, headers, Some(&body.to_string()))
+    }
+
+    pub fn get_json(&self, url: &str, headers: &[(&str, &str)]) -> Result<serde_json::Value, HttpError> {
+        self.send_with_retries(|| self.agent.get(url), headers, None)
+    }
+
+    fn send_with_retries(&self, request: impl Fn() -> ureq::Request, headers: &[(&str, &str)], body: Option<&str>)
+                         -> Result<serde_json::Value, HttpError> {
+        let mut attempt = 0;
+        loop {
+            let mut request = request();
+            for (name, value) in headers {
+                request = request.set(name, value);
+            }
+            let result = match body {
+                Some(body) => request.send_string(body),
+                None => request.call(),
+            };
+            match Self::handle_response(result) {
+                Ok(value) => return Ok(value),
+                Err(AttemptError::Fatal(error)) => return Err(error),
+                Err(AttemptError::Retryable { error, retry_after }) => {
+                    if attempt >= self.retry_policy.max_retries {
+                        return Err(error);
+                    }
+                    thread::sleep(self.retry_policy.delay(attempt, retry_after));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    fn handle_response(result: Result<ureq::Response, ureq::Error>) -> Result<serde_json::Value, AttemptError> {
+        match result {
+            Ok(response) => {
+                let text = response.into_string()
+                    .map_err(|e| AttemptError::Retryable { error: HttpError::Transport(e.to_string()), retry_after: None })?;
+                serde_json::from_str(&text)
+                    .map_err(|e| AttemptError::Fatal(HttpError::InvalidResponse(e.to_string())))
+            }
+            Err(ureq::Error::Status(code, response)) => {
+                let retry_after = response.header("Retry-After").and_then(parse_retry_after);
+                let text = response.into_string().unwrap_or_default();
+                let (error_code, message) = parse_error_body(&text);
+
+                if error_code.as_deref().is_some_and(|c| QUOTA_ERROR_CODES.contains(&c)) {
+                    return Err(AttemptError::Fatal(HttpError::QuotaExhausted(message)));
+                }
+
+                let error = HttpError::Status { code, message };
+                if code == 429 || code >= 500 {
+                    Err(AttemptError::Retryable { error, retry_after })
+                } else {
+                    Err(AttemptError::Fatal(error))
+                }
+            }
+            Err(ureq::Error::Transport(transport)) => Err(AttemptError::Retryable {
+                error: HttpError::Transport(transport.to_string()),
+                retry_after: None,
+            }),
+        }
+    }
+}
+
+/// Only the delta-seconds form of `Retry-After` is supported.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<f64>().ok()
+        .filter(|s| s.is_finite() && *s >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// Extracts error code and message from an OpenAI style error body
+/// (`{"error": {"message": ..., "code": ...}}`), falling back to the raw body.
+fn parse_error_body(text: &str) -> (Option<String>, String) {
+    let json: Option<serde_json::Value> = serde_json::from_str(text).ok();
+    let error = json.as_ref().and_then(|j| j.get("error"));
+    let code = error
+        .and_then(|e| e.get("code").or_else(|| e.get("type")))
+        .and_then(|c| c.as_str())
+        .map(|c| c.to_string());
+    let message = error
+        .and_then(|e| e.get("message"))
+        .and_then(|m| m.as_str())
+        .map(|m| m.to_string())
+        .unwrap_or_else(|| text.trim().to_string());
+    (code, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use super::*;
+
+    struct ScriptedResponse {
+        status: u16,
+        headers: Vec<(&'static str, &'static str)>,
+        body: &'static str,
+    }
+
+    fn response(status: u16, body: &'static str) -> ScriptedResponse {
+        ScriptedResponse { status, headers: vec![], body }
+    }
+
+    /// Starts a server that answers each incoming request with the next scripted response.
+    /// Returns the base url and a counter of received requests.
+    fn start_fake_server(responses: Vec<ScriptedResponse>) -> (String, Arc<Mutex<usize>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let counter = Arc::new(Mutex::new(0));
+        let thread_counter = counter.clone();
+
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                *thread_counter.lock().unwrap() += 1;
+
+                let mut raw = format!("HTTP/1.1 {} Scripted\r\nContent-Length: {}\r\nConnection: close\r\n",
+                                      response.status, response.body.len());
+                for (name, value) in response.headers {
+                    raw.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                raw.push_str("\r\n");
+                raw.push_str(response.body);
+                stream.write_all(raw.as_bytes()).unwrap();
+            }
+        });
+
+        (url, counter)
+    }
+
+    fn test_client_config(max_retries: u32) -> HttpConfig {
+        HttpConfig {
+            timeout_seconds: 5,
+            max_retries,
+            initial_backoff_millis: 1,
+            max_backoff_millis: 5,
+        }
+    }
+
+    fn test_client(max_retries: u32) -> HttpClient {
+        HttpClient::new(&test_client_config(max_retries))
+    }
+
+    #[test]
+    fn test_retries_until_success() {
+        let (url, counter) = start_fake_server(vec![
+            response(500, "oops"),
+            response(429, r#"{"error": {"message": "slow down", "code": "rate_limit_exceeded"}}"#),
+            response(200, r#"{"ok": true}"#),
+        ]);
+
+        let result = test_client(3).post_json(&url, &[], &serde_json::json!({}));
+
+        assert_eq!(result.unwrap(), serde_json::json!({"ok": true}));
+        assert_eq!(*counter.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_get_is_retried_too() {
+        let (url, counter) = start_fake_server(vec![
+            response(502, "bad gateway"),
+            response(200, r#"{"data": []}"#),
+        ]);
+
+        let result = test_client(1).get_json(&url, &[("Authorization", "Bearer sk-test")]);
+
+        assert_eq!(result.unwrap(), serde_json::json!({"data": []}));
+        assert_eq!(*counter.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_gives_up_after_max_retries() {
+        let (url, counter) = start_fake_server(vec![
+            response(503, "unavailable"),
+            response(503, "unavailable"),
+            response(503, "unavailable"),
+        ]);
+
+        let result = test_client(2).post_json(&url, &[], &serde_json::json!({}));
+
+        assert!(matches!(result, Err(HttpError::Status { code: 503, .. })), "got {:?}", result);
+        assert_eq!(*counter.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_client_error_is_not_retried() {
+        let (url, counter) = start_fake_server(vec![
+            response(401, r#"{"error": {"message": "bad key", "code": "invalid_api_key"}}"#),
+            response(200, "{}"),
+        ]);
+
+        let result = test_client(3).post_json(&url, &[], &serde_json::json!({}));
+
+        match result {
+            Err(HttpError::Status { code, message }) => {
+                assert_eq!(code, 401);
+                assert_eq!(message, "bad key");
+            }
+            other => panic!("expected status error, got {:?}", other),
+        }
+        assert_eq!(*counter.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_quota_exhausted_is_not_retried() {
+        let (url, counter) = start_fake_server(vec![
+            response(429, r#"{"error": {"message": "You exceeded your current quota", "code": "insufficient_quota"}}"#),
+            response(200, "{}"),
+        ]);
+
+        let result = test_client(3).post_json(&url, &[], &serde_json::json!({}));
+
+        assert!(matches!(result, Err(HttpError::QuotaExhausted(_))), "got {:?}", result);
+        assert_eq!(*counter.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_retry_after_header_is_honoured() {
+        let (url, _) = start_fake_server(vec![
+            ScriptedResponse { status: 429, headers: vec![("Retry-After", "0.2")], body: "" },
+            response(200, "{}"),
+        ]);
+
+        let client = HttpClient::new(&HttpConfig { max_backoff_millis: 1000, ..test_client_config(1) });
+        let start = std::time::Instant::now();
+        let result = client.post_json(&url, &[], &serde_json::json!({}));
+
+        assert!(result.is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_delay_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(400),
+        };
+        for attempt in 0..10 {
+            assert!(policy.delay(attempt, None) <= Duration::from_millis(400));
+        }
+        assert_eq!(policy.delay(0, Some(Duration::from_millis(300))), Duration::from_millis(300));
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(3600))), Duration::from_millis(400));
+    }
+}
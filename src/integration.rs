@@ -0,0 +1,31 @@
+/// Shells with an integration script, printed by `hu init <shell>`.
+pub const SUPPORTED_SHELLS: [&str; 4] = ["bash", "zsh", "fish", "powershell"];
+
+const INTEGRATIONS: [(&str, &str); 4] = [
+    ("bash", include_str!("integrations/bash.sh")),
+    ("zsh", include_str!("integrations/zsh.zsh")),
+    ("fish", include_str!("integrations/fish.fish")),
+    ("powershell", include_str!("integrations/powershell.ps1")),
+];
+
+/// The code that binds Ctrl-G in the given shell to replace the current line
+/// with a command generated from it (using `hu --print-only`).
+pub fn integration_script(shell: &str) -> Option<&'static str> {
+    INTEGRATIONS.iter()
+        .find(|(name, _)| *name == shell)
+        .map(|(_, script)| *script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_supported_shell_has_a_script() {
+        for shell in SUPPORTED_SHELLS {
+            let script = integration_script(shell).unwrap();
+            assert!(script.contains(&format!("hu --print-only --shell {} --", shell)), "{}", shell);
+        }
+        assert!(integration_script("cmd").is_none());
+    }
+}
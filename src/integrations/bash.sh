@@ -0,0 +1,11 @@
+# houston integration for bash, load it with: eval "$(hu init bash)"
+# Ctrl-G replaces the current line with a command generated from it.
+__houston_widget() {
+    [[ -z "$READLINE_LINE" ]] && return
+    local command
+    if command="$(hu --print-only --shell bash -- "$READLINE_LINE" </dev/null)" && [[ -n "$command" ]]; then
+        READLINE_LINE="$command"
+        READLINE_POINT=${#READLINE_LINE}
+    fi
+}
+bind -x '"\C-g": __houston_widget'
@@ -0,0 +1,14 @@
+# houston integration for fish, load it with: hu init fish | source
+# Ctrl-G replaces the current command line with a command generated from it.
+function __houston_widget
+    set -l instruction (commandline)
+    if test -z "$instruction"
+        return
+    end
+    set -l lines (hu --print-only --shell fish -- "$instruction" </dev/null)
+    if test $status -eq 0; and test -n "$lines"
+        commandline --replace -- (string join \n $lines)
+    end
+    commandline --function repaint
+end
+bind \cg __houston_widget
@@ -0,0 +1,12 @@
+# houston integration for PowerShell, load it with: hu init powershell | Out-String | Invoke-Expression
+# Ctrl+G replaces the current line with a command generated from it.
+Set-PSReadLineKeyHandler -Chord 'Ctrl+g' -BriefDescription 'houston' -Description 'Generate a command from the current line' -ScriptBlock {
+    $line = $null
+    $cursor = $null
+    [Microsoft.PowerShell.PSConsoleReadLine]::GetBufferState([ref]$line, [ref]$cursor)
+    if ([string]::IsNullOrWhiteSpace($line)) { return }
+    $command = (hu --print-only --shell powershell -- $line) -join "`n"
+    if ($LASTEXITCODE -eq 0 -and $command) {
+        [Microsoft.PowerShell.PSConsoleReadLine]::Replace(0, $line.Length, $command)
+    }
+}
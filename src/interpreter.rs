@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::prompt::lang_file_stem;
+
+/// Placeholder for the path of the script file in [Interpreter::command].
+const SCRIPT_PLACEHOLDER: &str = "{script}";
+
+/// How arguments are passed to the script.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ArgConvention {
+    /// Arguments follow the script path (`python script.py a b`).
+    #[default]
+    Append,
+    /// Arguments follow a `--` separator (`tool script -- a b`).
+    AfterSeparator,
+    /// The interpreter cannot pass arguments to the script, they are dropped.
+    Ignore,
+}
+
+/// Describes how to run a script file with a certain interpreter.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Interpreter {
+    /// The command line. `{script}` is replaced with the path of the script file.
+    /// Without `{script}`, the path is appended.
+    pub command: Vec<String>,
+    /// File name suffix of the script file, e.g. `.ps1`. Some interpreters refuse to run files without it.
+    #[serde(default)]
+    pub suffix: String,
+    #[serde(default)]
+    pub args: ArgConvention,
+    /// Other names this interpreter is known by, used for `--shell` values and shebangs.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+impl Interpreter {
+    fn new(command: &[&str], suffix: &str, aliases: &[&str]) -> Self {
+        Interpreter {
+            command: command.iter().map(|s| s.to_string()).collect(),
+            suffix: suffix.to_string(),
+            args: ArgConvention::Append,
+            aliases: aliases.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Interpreter for programs that are not in the registry: `<program> <script> <args>`.
+    fn fallback(program: &str) -> Self {
+        Interpreter::new(&[program, SCRIPT_PLACEHOLDER], "", &[])
+    }
+
+    /// The same interpreter, run by another program.
+    fn with_program(&self, program: &str) -> Self {
+        let mut interpreter = self.clone();
+        match interpreter.command.first_mut() {
+            Some(first) => *first = program.to_string(),
+            None => interpreter.command.push(program.to_string()),
+        }
+        interpreter
+    }
+
+    pub fn program(&self) -> &str {
+        self.command.first().map(|s| s.as_str()).unwrap_or_default()
+    }
+
+    /// The full command line (program first) to run the given script file with the given arguments.
+    pub fn command_line(&self, script_path: &str, args: &[&str]) -> Vec<String> {
+        let mut line = self.command.iter()
+            .map(|part| part.replace(SCRIPT_PLACEHOLDER, script_path))
+            .collect::<Vec<String>>();
+        if !self.command.iter().any(|part| part.contains(SCRIPT_PLACEHOLDER)) {
+            line.push(script_path.to_string());
+        }
+
+        match self.args {
+            ArgConvention::Append => line.extend(args.iter().map(|a| a.to_string())),
+            ArgConvention::AfterSeparator if !args.is_empty() => {
+                line.push("--".to_string());
+                line.extend(args.iter().map(|a| a.to_string()));
+            }
+            ArgConvention::AfterSeparator | ArgConvention::Ignore => {}
+        }
+        line
+    }
+}
+
+/// Known interpreters by name. Built-in entries can be overridden and extended in the config.
+#[derive(Debug, Clone)]
+pub struct InterpreterRegistry {
+    interpreters: BTreeMap<String, Interpreter>,
+}
+
+impl Default for InterpreterRegistry {
+    fn default() -> Self {
+        InterpreterRegistry { interpreters: built_in_interpreters() }
+    }
+}
+
+fn built_in_interpreters() -> BTreeMap<String, Interpreter> {
+    let python = if cfg!(windows) { "python" } else { "python3" };
+    let interpreters = [
+        ("bash", Interpreter::new(&["bash", SCRIPT_PLACEHOLDER], ".sh", &[])),
+        ("sh", Interpreter::new(&["sh", SCRIPT_PLACEHOLDER], ".sh", &["dash"])),
+        ("zsh", Interpreter::new(&["zsh", SCRIPT_PLACEHOLDER], ".zsh", &[])),
+        ("fish", Interpreter::new(&["fish", SCRIPT_PLACEHOLDER], ".fish", &[])),
+        ("powershell", Interpreter::new(
+            &["powershell", "-NoProfile", "-ExecutionPolicy", "Bypass", "-File", SCRIPT_PLACEHOLDER],
+            ".ps1", &[])),
+        ("pwsh", Interpreter::new(&["pwsh", "-NoProfile", "-File", SCRIPT_PLACEHOLDER], ".ps1", &[])),
+        ("cmd", Interpreter::new(&["cmd", "/c", SCRIPT_PLACEHOLDER], ".bat", &["bat", "batch"])),
+        ("python", Interpreter::new(&[python, SCRIPT_PLACEHOLDER], ".py", &["python3", "py"])),
+        ("node", Interpreter::new(&["node", SCRIPT_PLACEHOLDER], ".js", &["nodejs", "javascript", "js"])),
+        ("deno", Interpreter::new(&["deno", "run", "--allow-all", SCRIPT_PLACEHOLDER], ".ts", &["typescript", "ts"])),
+        ("ruby", Interpreter::new(&["ruby", SCRIPT_PLACEHOLDER], ".rb", &["rb"])),
+        ("perl", Interpreter::new(&["perl", SCRIPT_PLACEHOLDER], ".pl", &[])),
+        ("php", Interpreter::new(&["php", SCRIPT_PLACEHOLDER], ".php", &[])),
+    ];
+    interpreters.into_iter().map(|(name, i)| (name.to_string(), i)).collect()
+}
+
+impl InterpreterRegistry {
+    /// The built-in interpreters, with the given entries added or replaced.
+    pub fn with_overrides(overrides: &BTreeMap<String, Interpreter>) -> Self {
+        let mut registry = InterpreterRegistry::default();
+        registry.interpreters.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+        registry
+    }
+
+    /// Finds an interpreter by name or alias. Paths and `.exe` suffixes are ignored,
+    /// so `/usr/bin/python3` finds the `python` entry.
+    pub fn find(&self, name: &str) -> Option<&Interpreter> {
+        let name = lang_file_stem(name);
+        self.interpreters.get(&name)
+            .or_else(|| self.interpreters.values().find(|i| i.aliases.contains(&name)))
+    }
+
+    /// Like [InterpreterRegistry::find], but falls back to running `<name> <script>` for unknown names.
+    /// When the name is a path, that program is run, with the flags, suffix and argument convention of the entry,
+    /// so `/opt/homebrew/bin/bash` does not run the `bash` on the PATH.
+    pub fn resolve(&self, name: &str) -> Interpreter {
+        match self.find(name) {
+            Some(interpreter) if name.contains(['/', '\\']) => interpreter.with_program(name),
+            Some(interpreter) => interpreter.clone(),
+            None => Interpreter::fallback(name),
+        }
+    }
+
+    /// Finds the interpreter that the script's shebang line asks for.
+    pub fn find_by_shebang(&self, script: &str) -> Option<&Interpreter> {
+        parse_shebang(script).and_then(|name| self.find(&name))
+    }
+}
+
+/// Returns the interpreter name of a shebang line, e.g. `python3` for `#!/usr/bin/env python3`
+/// or `bash` for `#!/bin/bash -e`.
+pub fn parse_shebang(script: &str) -> Option<String> {
+    let line = script.lines().next()?.strip_prefix("#!")?;
+    let mut parts = line.split_whitespace();
+    let program = parts.next()?;
+
+    let name = if lang_file_stem(program) == "env" {
+        // skip env options like -S
+        parts.find(|p| !p.starts_with('-'))?
+    } else {
+        program
+    };
+    Some(lang_file_stem(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_by_name_alias_and_path() {
+        let registry = InterpreterRegistry::default();
+
+        assert_eq!(registry.find("bash").unwrap().suffix, ".sh");
+        assert_eq!(registry.find("python3").unwrap().suffix, ".py");
+        assert_eq!(registry.find("/usr/local/bin/node").unwrap().suffix, ".js");
+        assert_eq!(registry.find("PowerShell.exe").unwrap().suffix, ".ps1");
+        assert!(registry.find("unknown-lang").is_none());
+    }
+
+    #[test]
+    fn test_resolve_unknown_falls_back_to_program() {
+        let interpreter = InterpreterRegistry::default().resolve("my-shell");
+
+        assert_eq!(interpreter.command_line("/tmp/x", &["a"]), vec!["my-shell", "/tmp/x", "a"]);
+        assert_eq!(interpreter.suffix, "");
+    }
+
+    #[test]
+    fn test_resolve_path_keeps_the_program() {
+        let registry = InterpreterRegistry::default();
+
+        let bash = registry.resolve("/opt/homebrew/bin/bash");
+        assert_eq!(bash.command_line("s.sh", &["a"]), vec!["/opt/homebrew/bin/bash", "s.sh", "a"]);
+        assert_eq!(bash.suffix, ".sh");
+
+        let pwsh = registry.resolve("/opt/microsoft/powershell/7/pwsh");
+        assert_eq!(pwsh.command_line("s.ps1", &[]), vec!["/opt/microsoft/powershell/7/pwsh", "-NoProfile", "-File", "s.ps1"]);
+        assert_eq!(registry.resolve("bash").program(), "bash");
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut overrides = BTreeMap::new();
+        overrides.insert("python".to_string(), Interpreter::new(&["uv", "run", "{script}"], ".py", &["py"]));
+        overrides.insert("lua".to_string(), Interpreter::new(&["lua"], ".lua", &[]));
+
+        let registry = InterpreterRegistry::with_overrides(&overrides);
+
+        assert_eq!(registry.find("python").unwrap().program(), "uv");
+        assert_eq!(registry.find("lua").unwrap().command_line("s.lua", &[]), vec!["lua", "s.lua"]);
+        assert!(registry.find("bash").is_some());
+    }
+
+    #[test]
+    fn test_command_line_conventions() {
+        let registry = InterpreterRegistry::default();
+
+        assert_eq!(registry.resolve("deno").command_line("s.ts", &["a", "b"]),
+                   vec!["deno", "run", "--allow-all", "s.ts", "a", "b"]);
+        assert_eq!(registry.resolve("cmd").command_line("s.bat", &[]), vec!["cmd", "/c", "s.bat"]);
+
+        let mut separator = Interpreter::new(&["tool", "{script}"], "", &[]);
+        separator.args = ArgConvention::AfterSeparator;
+        assert_eq!(separator.command_line("s", &["a"]), vec!["tool", "s", "--", "a"]);
+        assert_eq!(separator.command_line("s", &[]), vec!["tool", "s"]);
+
+        let mut ignore = Interpreter::new(&["tool"], "", &[]);
+        ignore.args = ArgConvention::Ignore;
+        assert_eq!(ignore.command_line("s", &["a"]), vec!["tool", "s"]);
+    }
+
+    #[test]
+    fn test_parse_shebang() {
+        assert_eq!(parse_shebang("#!/usr/bin/env python3\nprint(1)"), Some("python3".to_string()));
+        assert_eq!(parse_shebang("#!/bin/bash -e\necho"), Some("bash".to_string()));
+        assert_eq!(parse_shebang("#!/usr/bin/env -S deno run\n"), Some("deno".to_string()));
+        assert_eq!(parse_shebang("#! /bin/sh"), Some("sh".to_string()));
+        assert_eq!(parse_shebang("echo hi"), None);
+        assert_eq!(parse_shebang(""), None);
+    }
+
+    #[test]
+    fn test_find_by_shebang() {
+        let registry = InterpreterRegistry::default();
+        let interpreter = registry.find_by_shebang("#!/usr/bin/env node\nconsole.log(1)").unwrap();
+        assert_eq!(interpreter.program(), "node");
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde_yaml::{Mapping, Value};
+
+use crate::config_file::{ConfigProblem, validate, validate_project};
+use crate::migration::{migrate_value, migrations};
+use crate::profile::PROFILE_ENV_VAR;
+
+/// Environment variables starting with this prefix set config keys, e.g. `HOUSTON_DEFAULT_RUN_MODE=dry`.
+/// Nested keys are separated by a double underscore: `HOUSTON_OPEN_AI__MODEL=gpt-4o`.
+pub const ENV_PREFIX: &str = "HOUSTON_";
+/// Environment variables with the prefix that are not config keys.
+const RESERVED_ENV_VARS: [&str; 1] = [PROFILE_ENV_VAR];
+
+/// Where the effective value of a config key comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigOrigin {
+    Default,
+    System(PathBuf),
+    User(PathBuf),
+    Project(PathBuf),
+    Profile(String),
+    Env(String),
+}
+
+impl fmt::Display for ConfigOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigOrigin::Default => write!(f, "default"),
+            ConfigOrigin::System(path) => write!(f, "system ({})", path.display()),
+            ConfigOrigin::User(path) => write!(f, "user ({})", path.display()),
+            ConfigOrigin::Project(path) => write!(f, "project ({})", path.display()),
+            ConfigOrigin::Profile(name) => write!(f, "profile ({})", name),
+            ConfigOrigin::Env(name) => write!(f, "env ({})", name),
+        }
+    }
+}
+
+/// One source of configuration. Later layers override earlier ones key by key.
+#[derive(Debug, Clone)]
+pub struct ConfigLayer {
+    pub origin: ConfigOrigin,
+    pub value: Value,
+}
+
+impl ConfigLayer {
+    /// The layer of a config file, or `None` if the file does not exist.
+    pub fn from_file(path: &Path, origin: ConfigOrigin) -> Result<Option<Self>, String> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let problems = match origin {
+            ConfigOrigin::Project(_) => validate_project(&content),
+            _ => validate(&content),
+        };
+        if !problems.is_empty() {
+            return Err(format_problems(&path.display().to_string(), &problems));
+        }
+        let mut value: Value = serde_yaml::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        // files that are not migrated in place (e.g. the system config) are migrated when they are read
+        migrate_value(&mut value, &migrations()).map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        Ok(Some(ConfigLayer { origin, value }))
+    }
+}
+
+/// `Invalid config <source>:` followed by one problem per line.
+pub fn format_problems(source: &str, problems: &[ConfigProblem]) -> String {
+    let mut result = format!("Invalid config {}:", source);
+    for problem in problems {
+        result.push_str(&format!("\n  {}", problem));
+    }
+    result
+}
+
+/// The configuration that is shared by all users of the machine.
+pub fn system_config_path() -> PathBuf {
+    if cfg!(windows) {
+        let program_data = std::env::var_os("ProgramData").unwrap_or_else(|| "C:\\ProgramData".into());
+        PathBuf::from(program_data).join("houston").join("config.yml")
+    } else {
+        PathBuf::from("/etc/houston/config.yml")
+    }
+}
+
+/// One layer per `HOUSTON_*` variable, in name order.
+pub fn env_layers(vars: impl Iterator<Item=(String, String)>) -> Vec<ConfigLayer> {
+    let mut vars = vars
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX) && !RESERVED_ENV_VARS.contains(&name.as_str()))
+        .collect::<Vec<(String, String)>>();
+    vars.sort();
+
+    vars.into_iter().map(|(name, raw)| {
+        let parsed = parse_scalar(&raw);
+        let path = env_key_path(&name[ENV_PREFIX.len()..]);
+        let value = path.iter().rev().fold(parsed, |value, key| {
+            let mut mapping = Mapping::new();
+            mapping.insert(Value::String(key.clone()), value);
+            Value::Mapping(mapping)
+        });
+        ConfigLayer { origin: ConfigOrigin::Env(name), value }
+    }).collect()
+}
+
+/// Parses a value given as text (in an environment variable or on the command line):
+/// `true`, `0.5` or `[a, b]` get their YAML type, everything else stays a string.
+pub fn parse_scalar(raw: &str) -> Value {
+    serde_yaml::from_str::<Value>(raw).ok()
+        .filter(|v| !matches!(v, Value::Mapping(_) | Value::Null))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+/// `OPEN_AI__BASE_URL` -> `["openAi", "baseUrl"]`
+fn env_key_path(name: &str) -> Vec<String> {
+    name.split("__").map(camel_case).collect()
+}
+
+fn camel_case(snake: &str) -> String {
+    let mut result = String::new();
+    for (i, part) in snake.split('_').filter(|p| !p.is_empty()).enumerate() {
+        let part = part.to_lowercase();
+        if i == 0 {
+            result.push_str(&part);
+        } else {
+            let mut chars = part.chars();
+            if let Some(first) = chars.next() {
+                result.extend(first.to_uppercase());
+                result.push_str(chars.as_str());
+            }
+        }
+    }
+    result
+}
+
+/// The result of merging all layers, with the origin of every leaf value.
+#[derive(Debug, Clone)]
+pub struct MergedConfig {
+    pub value: Value,
+    pub origins: BTreeMap<Vec<String>, ConfigOrigin>,
+}
+
+impl MergedConfig {
+    /// All leaf values with their key path and origin, in the order of the merged document.
+    pub fn entries(&self) -> Vec<(Vec<String>, &Value, Option<&ConfigOrigin>)> {
+        leaves(&self.value, &mut vec![]).into_iter()
+            .map(|(path, value)| {
+                let origin = self.origins.get(&path);
+                (path, value, origin)
+            })
+            .collect()
+    }
+}
+
+/// Merges the layers in order: mappings are merged key by key, other values replace the earlier ones.
+/// `null` values leave the earlier value in place.
+pub fn merge(layers: &[ConfigLayer]) -> MergedConfig {
+    let mut merged = MergedConfig { value: Value::Mapping(Mapping::new()), origins: BTreeMap::new() };
+    for layer in layers {
+        merge_value(&mut merged.value, &layer.value, &mut vec![], &layer.origin, &mut merged.origins);
+    }
+    merged
+}
+
+fn merge_value(target: &mut Value, source: &Value, path: &mut Vec<String>, origin: &ConfigOrigin,
+               origins: &mut BTreeMap<Vec<String>, ConfigOrigin>) {
+    match (target, source) {
+        (_, Value::Null) => {}
+        (Value::Mapping(target), Value::Mapping(source)) => {
+            for (key, value) in source {
+                path.push(key_string(key));
+                let entry = target.entry(key.clone()).or_insert(Value::Null);
+                merge_value(entry, value, path, origin, origins);
+                path.pop();
+            }
+        }
+        (target, source) => {
+            // a replaced mapping takes the origins of its children with it
+            origins.retain(|p, _| !(p.len() > path.len() && p.starts_with(path)));
+            *target = source.clone();
+            for (leaf, _) in leaves(source, &mut path.clone()) {
+                origins.insert(leaf, origin.clone());
+            }
+        }
+    }
+}
+
+/// The key paths of all leaf values, e.g. `["openAi", "model"]`.
+pub fn leaf_paths(value: &Value) -> Vec<Vec<String>> {
+    leaves(value, &mut vec![]).into_iter().map(|(path, _)| path).collect()
+}
+
+fn leaves<'a>(value: &'a Value, path: &mut Vec<String>) -> Vec<(Vec<String>, &'a Value)> {
+    match value {
+        Value::Mapping(mapping) if !mapping.is_empty() => {
+            let mut result = Vec::new();
+            for (key, value) in mapping {
+                path.push(key_string(key));
+                result.extend(leaves(value, path));
+                path.pop();
+            }
+            result
+        }
+        _ => vec![(path.clone(), value)],
+    }
+}
+
+fn key_string(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other).unwrap_or_default().trim().to_string(),
+    }
+}
+
+/// A value on a single line, e.g. `[git, docker]` for a sequence.
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => "~".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Sequence(items) => format!("[{}]", items.iter().map(format_value).collect::<Vec<String>>().join(", ")),
+        Value::Mapping(mapping) if mapping.is_empty() => "{}".to_string(),
+        other => serde_yaml::to_string(other).unwrap_or_default().trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(yaml: &str, origin: ConfigOrigin) -> ConfigLayer {
+        ConfigLayer { origin, value: serde_yaml::from_str(yaml).unwrap() }
+    }
+
+    fn path(key: &str) -> Vec<String> {
+        key.split('.').map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_later_layers_win_key_by_key() {
+        let user = ConfigOrigin::User(PathBuf::from("config.yml"));
+        let merged = merge(&[
+            layer("defaultShell: bash\nopenAi:\n  model: gpt-4\n  temperature: 0.5", ConfigOrigin::Default),
+            layer("openAi:\n  model: gpt-4o\ndefaultShell: null", user.clone()),
+        ]);
+
+        assert_eq!(merged.value["openAi"]["model"], Value::String("gpt-4o".to_string()));
+        assert_eq!(merged.value["defaultShell"], Value::String("bash".to_string()));
+        assert_eq!(merged.origins[&path("openAi.model")], user);
+        assert_eq!(merged.origins[&path("openAi.temperature")], ConfigOrigin::Default);
+        assert_eq!(merged.origins[&path("defaultShell")], ConfigOrigin::Default);
+    }
+
+    #[test]
+    fn test_env_layers() {
+        let vars = vec![
+            ("HOUSTON_OPEN_AI__BASE_URL".to_string(), "http://localhost:8080".to_string()),
+            ("HOUSTON_EXAMPLES__MAX_COUNT".to_string(), "5".to_string()),
+            ("HOUSTON_PROFILE".to_string(), "work".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ];
+
+        let merged = merge(&env_layers(vars.into_iter()));
+
+        assert_eq!(merged.value["openAi"]["baseUrl"], Value::String("http://localhost:8080".to_string()));
+        assert_eq!(merged.value["examples"]["maxCount"], Value::Number(5.into()));
+        assert_eq!(merged.origins[&path("examples.maxCount")], ConfigOrigin::Env("HOUSTON_EXAMPLES__MAX_COUNT".to_string()));
+        assert_eq!(merged.origins.len(), 2);
+    }
+
+    #[test]
+    fn test_project_layer_cannot_set_the_endpoint_or_key_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".houston").join("config.yml");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "openAi:\n  baseUrl: http://evil.example\n  apiKeyCommand: touch pwned\n").unwrap();
+
+        let err = ConfigLayer::from_file(&path, ConfigOrigin::Project(path.clone())).unwrap_err();
+        assert!(err.contains("openAi cannot be set in a project config"), "{}", err);
+
+        // the same file is fine as a user config
+        assert!(ConfigLayer::from_file(&path, ConfigOrigin::User(path.clone())).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_format_value() {
+        let value: Value = serde_yaml::from_str("[git, 1, true]").unwrap();
+        assert_eq!(format_value(&value), "[git, 1, true]");
+    }
+}
@@ -1,6 +1,28 @@
+pub mod api_key;
+pub mod attach;
+pub mod check;
 pub mod config;
+pub mod config_file;
 pub mod generator;
+pub mod highlight;
+pub mod history;
+pub mod http;
+pub mod integration;
+pub mod interpreter;
+pub mod layers;
+pub mod migration;
+pub mod prompt;
+pub mod profile;
+pub mod provider;
+pub mod redact;
+pub mod repl;
 pub mod runner;
+pub mod setup;
 pub mod template;
 pub mod context;
-pub mod tmp_file;
\ No newline at end of file
+pub mod diff;
+pub mod environment;
+pub mod examples;
+pub mod tmp_file;
+pub mod tokens;
+pub mod usage;
\ No newline at end of file
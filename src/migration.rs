@@ -0,0 +1,217 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_yaml::{Mapping, Value};
+
+/// The version of the config format. Configs without a `version` key are version 1.
+pub const CONFIG_VERSION: u32 = 2;
+const VERSION_KEY: &str = "version";
+
+/// Upgrades a config document from the previous version to `version`.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Mapping),
+}
+
+/// All migrations, by version.
+pub fn migrations() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 2,
+            description: "remove `openAi.apiKey: null`, which older versions wrote into new configs",
+            apply: remove_null_api_key,
+        },
+    ]
+}
+
+fn remove_null_api_key(config: &mut Mapping) {
+    let Some(Value::Mapping(open_ai)) = config.get_mut("openAi") else {
+        return;
+    };
+    if open_ai.get("apiKey").is_some_and(|key| key.is_null()) {
+        open_ai.remove("apiKey");
+    }
+    if open_ai.is_empty() {
+        config.remove("openAi");
+    }
+}
+
+/// The version of a config document.
+pub fn version_of(config: &Value) -> Result<u32, String> {
+    match config.get(VERSION_KEY) {
+        None => Ok(1),
+        Some(Value::Number(n)) if n.as_u64().is_some_and(|v| (1..=CONFIG_VERSION as u64).contains(&v)) => {
+            Ok(n.as_u64().unwrap() as u32)
+        }
+        Some(Value::Number(n)) if n.as_u64().is_some_and(|v| v > CONFIG_VERSION as u64) => {
+            Err(format!("version {} is newer than the config versions this version of houston understands (up to {}). \
+                         Please update houston.", n, CONFIG_VERSION))
+        }
+        Some(other) => Err(format!("invalid version {:?}, expected a number from 1 to {}", other, CONFIG_VERSION)),
+    }
+}
+
+/// Upgrades a config document to the current version.
+/// Returns the descriptions of the migrations that changed something.
+pub fn migrate_value(config: &mut Value, migrations: &[Migration]) -> Result<Vec<&'static str>, String> {
+    let version = version_of(config)?;
+    if version == CONFIG_VERSION {
+        return Ok(vec![]);
+    }
+    let Value::Mapping(mapping) = config else {
+        // an empty file
+        return Ok(vec![]);
+    };
+    let mut applied = Vec::new();
+    for migration in migrations.iter().filter(|m| m.version > version) {
+        let before = mapping.clone();
+        (migration.apply)(mapping);
+        if *mapping != before {
+            applied.push(migration.description);
+        }
+    }
+    mapping.insert(Value::String(VERSION_KEY.to_string()), Value::Number(CONFIG_VERSION.into()));
+    Ok(applied)
+}
+
+/// The text of a config file upgraded to the current version.
+pub struct MigratedConfig {
+    pub from_version: u32,
+    pub text: String,
+    pub applied: Vec<&'static str>,
+}
+
+/// Upgrades the text of a config file, or returns `None` if it is up to date.
+/// If only the version changes, the text (with its comments) is kept and the version is put in front.
+pub fn migrate_text(text: &str, migrations: &[Migration]) -> Result<Option<MigratedConfig>, String> {
+    let original: Value = serde_yaml::from_str(text).map_err(|e| e.to_string())?;
+    let from_version = version_of(&original)?;
+    if from_version == CONFIG_VERSION || !original.is_mapping() {
+        return Ok(None);
+    }
+    let mut migrated = original.clone();
+    let applied = migrate_value(&mut migrated, migrations)?;
+
+    let mut unversioned = migrated.clone();
+    if let Value::Mapping(mapping) = &mut unversioned {
+        mapping.remove(VERSION_KEY);
+    }
+    let text = if unversioned == original {
+        format!("{}: {}\n{}", VERSION_KEY, CONFIG_VERSION, text)
+    } else {
+        serialize_version_first(&migrated)
+    };
+    Ok(Some(MigratedConfig { from_version, text, applied }))
+}
+
+fn serialize_version_first(config: &Value) -> String {
+    let mut ordered = Mapping::new();
+    if let Value::Mapping(mapping) = config {
+        ordered.insert(Value::String(VERSION_KEY.to_string()), Value::Number(CONFIG_VERSION.into()));
+        for (key, value) in mapping {
+            if key.as_str() != Some(VERSION_KEY) {
+                ordered.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    serde_yaml::to_string(&Value::Mapping(ordered)).unwrap_or_default()
+}
+
+/// Where the original of a migrated config file is kept, e.g. `config.yml.v1.bak`.
+/// Existing backups are kept, so the next ones are numbered: `config.yml.v1-2.bak`, `config.yml.v1-3.bak`, ...
+pub fn backup_path(path: &Path, from_version: u32) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    (1..)
+        .map(|n| match n {
+            1 => format!("{}.v{}.bak", file_name, from_version),
+            n => format!("{}.v{}-{}.bak", file_name, from_version, n),
+        })
+        .map(|name| path.with_file_name(name))
+        .find(|backup| !backup.exists())
+        .unwrap()
+}
+
+/// Copies the config file to its backup path before it is migrated. Returns the backup path.
+pub fn backup(path: &Path, from_version: u32) -> io::Result<PathBuf> {
+    let backup = backup_path(path, from_version);
+    std::fs::copy(path, &backup)?;
+    Ok(backup)
+}
+
+/// Replaces the file with the migrated config after copying the original to its backup path.
+/// Returns the backup path.
+pub fn write_migrated(path: &Path, migrated: &MigratedConfig) -> io::Result<PathBuf> {
+    let backup = backup(path, migrated.from_version)?;
+    std::fs::write(path, &migrated.text)?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename_run_mode(config: &mut Mapping) {
+        if let Some(mode) = config.remove("runMode") {
+            config.insert(Value::String("defaultRunMode".to_string()), mode);
+        }
+    }
+
+    #[test]
+    fn test_migrations_run_from_the_file_version() {
+        let migrations = vec![Migration { version: 2, description: "rename runMode", apply: rename_run_mode }];
+
+        let mut config: Value = serde_yaml::from_str("runMode: dry\n").unwrap();
+        let applied = migrate_value(&mut config, &migrations).unwrap();
+        assert_eq!(applied, vec!["rename runMode"]);
+        assert_eq!(serde_yaml::to_string(&config).unwrap(), format!("defaultRunMode: dry\nversion: {}\n", CONFIG_VERSION));
+
+        // a migration that does not change anything is not reported
+        let mut unchanged: Value = serde_yaml::from_str("defaultRunMode: dry\n").unwrap();
+        assert!(migrate_value(&mut unchanged, &migrations).unwrap().is_empty());
+        assert_eq!(unchanged.get("version"), Some(&Value::Number(CONFIG_VERSION.into())));
+
+        let mut current: Value = serde_yaml::from_str(&format!("version: {}\nrunMode: dry\n", CONFIG_VERSION)).unwrap();
+        assert!(migrate_value(&mut current, &migrations).unwrap().is_empty());
+        assert!(current.get("runMode").is_some());
+    }
+
+    #[test]
+    fn test_migrate_text_keeps_comments_if_only_the_version_changes() {
+        let text = "# my config\ndefaultRunMode: dry\n";
+        let migrated = migrate_text(text, &migrations()).unwrap().unwrap();
+        assert_eq!(migrated.from_version, 1);
+        assert_eq!(migrated.text, format!("version: {}\n# my config\ndefaultRunMode: dry\n", CONFIG_VERSION));
+
+        let migrated = migrate_text("defaultShell: bash\nopenAi:\n  apiKey: null\n", &migrations()).unwrap().unwrap();
+        assert_eq!(migrated.text, format!("version: {}\ndefaultShell: bash\n", CONFIG_VERSION));
+        assert_eq!(migrated.applied.len(), 1);
+
+        assert!(migrate_text(&migrated.text, &migrations()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_newer_versions_are_rejected() {
+        let config: Value = serde_yaml::from_str(&format!("version: {}\n", CONFIG_VERSION + 1)).unwrap();
+        assert!(version_of(&config).unwrap_err().contains("Please update houston"));
+    }
+
+    #[test]
+    fn test_backup_path() {
+        assert_eq!(backup_path(Path::new("/home/me/.config/houston/config.yml"), 1),
+                   PathBuf::from("/home/me/.config/houston/config.yml.v1.bak"));
+    }
+
+    #[test]
+    fn test_existing_backups_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yml");
+        std::fs::write(&path, "first\n").unwrap();
+        assert_eq!(backup(&path, 1).unwrap(), dir.path().join("config.yml.v1.bak"));
+        std::fs::write(&path, "second\n").unwrap();
+
+        assert_eq!(backup(&path, 1).unwrap(), dir.path().join("config.yml.v1-2.bak"));
+        assert_eq!(std::fs::read_to_string(dir.path().join("config.yml.v1.bak")).unwrap(), "first\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("config.yml.v1-2.bak")).unwrap(), "second\n");
+    }
+}
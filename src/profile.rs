@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use globset::GlobBuilder;
+use serde_yaml::Value;
+
+use crate::config::{expand_home, Profile};
+use crate::config_file::suggest;
+use crate::layers::{ConfigLayer, ConfigOrigin};
+
+/// Selects a profile by name, like `--profile`.
+pub const PROFILE_ENV_VAR: &str = "HOUSTON_PROFILE";
+
+/// Why a profile is used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectedBy {
+    Option,
+    Env,
+    /// The working directory matches this glob of the profile.
+    Directory(String),
+}
+
+impl fmt::Display for SelectedBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectedBy::Option => write!(f, "--profile"),
+            SelectedBy::Env => write!(f, "{}", PROFILE_ENV_VAR),
+            SelectedBy::Directory(glob) => write!(f, "directory {}", glob),
+        }
+    }
+}
+
+/// The profiles defined in a (merged) config document.
+pub fn profiles(config: &Value) -> Result<BTreeMap<String, Profile>, String> {
+    match config.get("profiles") {
+        None | Some(Value::Null) => Ok(BTreeMap::new()),
+        Some(profiles) => serde_yaml::from_value(profiles.clone()).map_err(|e| format!("Invalid profiles: {}", e)),
+    }
+}
+
+/// The profile to use: the one named by `--profile` (`option`), else by `HOUSTON_PROFILE` (`env`),
+/// else the one whose directories match the working directory.
+/// If several match, the one matching the deepest directory wins, then the first by name.
+pub fn select_profile(profiles: &BTreeMap<String, Profile>, option: Option<&str>, env: Option<&str>,
+                      cwd: &Path, home: Option<&Path>) -> Result<Option<(String, SelectedBy)>, String> {
+    let requested = option.map(|name| (name, SelectedBy::Option))
+        .or_else(|| env.filter(|name| !name.is_empty()).map(|name| (name, SelectedBy::Env)));
+    if let Some((name, selected_by)) = requested {
+        if !profiles.contains_key(name) {
+            return Err(unknown_profile(name, &selected_by, profiles));
+        }
+        return Ok(Some((name.to_string(), selected_by)));
+    }
+
+    for dir in cwd.ancestors() {
+        for (name, profile) in profiles {
+            for pattern in &profile.directories {
+                if glob_matches(&expand_home(pattern, home), dir)? {
+                    return Ok(Some((name.clone(), SelectedBy::Directory(pattern.clone()))));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The overrides of the selected profile as a config layer, or `None` if no profile is selected.
+pub fn profile_layer(config: &Value, option: Option<&str>) -> Result<Option<ConfigLayer>, String> {
+    let profiles = profiles(config)?;
+    if profiles.is_empty() && option.is_none() {
+        return Ok(None);
+    }
+    let env = std::env::var(PROFILE_ENV_VAR).ok();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let selected = select_profile(&profiles, option, env.as_deref(), &cwd, dirs::home_dir().as_deref())?;
+    Ok(selected.map(|(name, _)| ConfigLayer {
+        value: Value::Mapping(profiles[&name].overrides.clone()),
+        origin: ConfigOrigin::Profile(name),
+    }))
+}
+
+fn unknown_profile(name: &str, selected_by: &SelectedBy, profiles: &BTreeMap<String, Profile>) -> String {
+    let names = profiles.keys().map(|k| k.as_str()).collect::<Vec<&str>>();
+    let mut message = format!("Unknown profile {} (from {})", name, selected_by);
+    if let Some(suggestion) = suggest(name, &names) {
+        message.push_str(&format!(", did you mean {}?", suggestion));
+    } else if names.is_empty() {
+        message.push_str(", no profiles are configured");
+    } else {
+        message.push_str(&format!(", available profiles: {}", names.join(", ")));
+    }
+    message
+}
+
+fn glob_matches(pattern: &str, dir: &Path) -> Result<bool, String> {
+    let pattern = pattern.trim_end_matches('/');
+    let glob = GlobBuilder::new(if pattern.is_empty() { "/" } else { pattern })
+        .literal_separator(true)
+        .build()
+        .map_err(|e| format!("Invalid profile directory {}: {}", pattern, e))?;
+    Ok(glob.compile_matcher().is_match(PathBuf::from(dir)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiles() -> BTreeMap<String, Profile> {
+        serde_yaml::from_str("work:\n  directories: ['~/work']\n  defaultShell: pwsh\n\
+            client:\n  directories: ['~/work/clients/*']\n\
+            personal:\n  openAi:\n    model: gpt-4o\n").unwrap()
+    }
+
+    fn select(option: Option<&str>, env: Option<&str>, cwd: &str) -> Result<Option<(String, SelectedBy)>, String> {
+        select_profile(&profiles(), option, env, Path::new(cwd), Some(Path::new("/home/me")))
+    }
+
+    #[test]
+    fn test_select_by_directory() {
+        assert_eq!(select(None, None, "/home/me/work/houston/src").unwrap(),
+                   Some(("work".to_string(), SelectedBy::Directory("~/work".to_string()))));
+        // the deeper match wins
+        assert_eq!(select(None, None, "/home/me/work/clients/acme/app").unwrap().unwrap().0, "client");
+        assert_eq!(select(None, None, "/home/me/workshop").unwrap(), None);
+    }
+
+    #[test]
+    fn test_select_by_name() {
+        assert_eq!(select(Some("personal"), Some("client"), "/home/me/work").unwrap(),
+                   Some(("personal".to_string(), SelectedBy::Option)));
+        assert_eq!(select(None, Some("personal"), "/home/me/work").unwrap(),
+                   Some(("personal".to_string(), SelectedBy::Env)));
+        assert_eq!(select(None, Some("personl"), "/").unwrap_err(),
+                   "Unknown profile personl (from HOUSTON_PROFILE), did you mean personal?");
+    }
+
+    #[test]
+    fn test_profile_overrides_are_kept() {
+        let profiles = profiles();
+        assert_eq!(profiles["work"].overrides.get("defaultShell"), Some(&Value::String("pwsh".to_string())));
+        assert!(profiles["work"].overrides.get("directories").is_none());
+    }
+}
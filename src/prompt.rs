@@ -0,0 +1,233 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::config::get_houston_dir;
+
+const PROMPTS_DIR_NAME: &str = "prompts";
+
+/// The template for a single command line that goes into the prompt of an interactive shell, see [PromptTemplate::load_command].
+const COMMAND_TEMPLATE: (&str, &str) = ("command", include_str!("prompts/command.txt"));
+
+const BUILT_IN_TEMPLATES: [(&str, &str); 6] = [
+    ("generic", include_str!("prompts/generic.txt")),
+    ("bash", include_str!("prompts/bash.txt")),
+    ("zsh", include_str!("prompts/zsh.txt")),
+    ("sh", include_str!("prompts/sh.txt")),
+    ("powershell", include_str!("prompts/powershell.txt")),
+    ("python", include_str!("prompts/python.txt")),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptTemplateSource {
+    File(PathBuf),
+    BuiltIn(&'static str),
+}
+
+impl Display for PromptTemplateSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromptTemplateSource::File(path) => write!(f, "{}", path.display()),
+            PromptTemplateSource::BuiltIn(name) => write!(f, "built-in ({})", name),
+        }
+    }
+}
+
+/// Template for the system message.
+/// Supports the placeholders `{{lang}}`, `{{os}}`, `{{shell_version}}` and `{{requirements}}`.
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    pub source: PromptTemplateSource,
+    pub text: String,
+    shell_version: OnceLock<String>,
+}
+
+/// Values for the placeholders of a [PromptTemplate].
+pub struct PromptVariables<'a> {
+    pub lang: &'a str,
+    pub os: &'a str,
+    pub requirements: &'a str,
+}
+
+impl PromptTemplate {
+    pub fn new(source: PromptTemplateSource, text: String) -> Self {
+        PromptTemplate {
+            source,
+            text,
+            shell_version: OnceLock::new(),
+        }
+    }
+
+    /// Loads the template for the given language from the houston prompts dir,
+    /// falling back to the built-in template.
+    pub fn load(lang: &str) -> io::Result<PromptTemplate> {
+        Self::load_from(&get_houston_dir().join(PROMPTS_DIR_NAME), lang)
+    }
+
+    /// Looks for `<lang>.txt` and `<canonical lang>.txt` in `dir`
+    /// (e.g. `python3.txt`, then `python.txt`), then for a built-in template.
+    pub fn load_from(dir: &Path, lang: &str) -> io::Result<PromptTemplate> {
+        let name = lang_file_stem(lang);
+        let canonical = canonical_lang(&name);
+
+        for candidate in [name.as_str(), canonical] {
+            let path = dir.join(format!("{}.txt", candidate));
+            if path.exists() {
+                let text = std::fs::read_to_string(&path)?;
+                return Ok(PromptTemplate::new(PromptTemplateSource::File(path), text));
+            }
+        }
+
+        let (name, text) = BUILT_IN_TEMPLATES.iter()
+            .find(|(name, _)| *name == canonical)
+            .unwrap_or(&BUILT_IN_TEMPLATES[0]);
+
+        Ok(PromptTemplate::new(PromptTemplateSource::BuiltIn(name), text.to_string()))
+    }
+
+    /// Loads the template for a single interactive command (used by `--print-only` and the shell integration)
+    /// from `command.txt` in the houston prompts dir, falling back to the built-in one.
+    /// Unlike the script templates, it asks for no shebang and no strict mode, which would stay set in the shell.
+    pub fn load_command() -> io::Result<PromptTemplate> {
+        Self::load_command_from(&get_houston_dir().join(PROMPTS_DIR_NAME))
+    }
+
+    pub fn load_command_from(dir: &Path) -> io::Result<PromptTemplate> {
+        let path = dir.join(format!("{}.txt", COMMAND_TEMPLATE.0));
+        if path.exists() {
+            let text = std::fs::read_to_string(&path)?;
+            return Ok(PromptTemplate::new(PromptTemplateSource::File(path), text));
+        }
+        Ok(PromptTemplate::new(PromptTemplateSource::BuiltIn(COMMAND_TEMPLATE.0), COMMAND_TEMPLATE.1.to_string()))
+    }
+
+    /// Whether the requirements are part of the system message.
+    /// If not, they are appended to the user message.
+    pub fn uses_requirements(&self) -> bool {
+        self.text.contains("{{requirements}}")
+    }
+
+    pub fn render(&self, variables: &PromptVariables) -> String {
+        let mut result = self.text
+            .replace("{{lang}}", variables.lang)
+            .replace("{{os}}", variables.os)
+            .replace("{{requirements}}", variables.requirements);
+
+        // only run the shell if the template actually needs its version, and only once
+        if result.contains("{{shell_version}}") {
+            let version = self.shell_version.get_or_init(|| {
+                detect_shell_version(variables.lang).unwrap_or_else(|| variables.lang.to_string())
+            });
+            result = result.replace("{{shell_version}}", version);
+        }
+
+        result.trim_end().to_string()
+    }
+}
+
+/// `/usr/bin/python3` -> `python3`, `pwsh.exe` -> `pwsh`
+pub(crate) fn lang_file_stem(lang: &str) -> String {
+    Path::new(lang)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| lang.to_lowercase())
+}
+
+pub(crate) fn canonical_lang(name: &str) -> &str {
+    match name {
+        "pwsh" => "powershell",
+        "python3" | "py" => "python",
+        "dash" => "sh",
+        other => other,
+    }
+}
+
+/// Asks the interpreter for its version, e.g. `GNU bash, version 5.2.15(1)-release`.
+pub fn detect_shell_version(lang: &str) -> Option<String> {
+    let output = match canonical_lang(&lang_file_stem(lang)) {
+        "powershell" => Command::new(lang)
+            .args(["-NoProfile", "-Command", "$PSVersionTable.PSVersion.ToString()"])
+            .output(),
+        "sh" => return None,
+        _ => Command::new(lang).arg("--version").output(),
+    }.ok()?;
+
+    // some interpreters (e.g. older pythons) print their version to stderr
+    let text = if output.stdout.is_empty() { output.stderr } else { output.stdout };
+    String::from_utf8_lossy(&text)
+        .lines()
+        .map(|l| l.trim())
+        .find(|l| !l.is_empty())
+        .map(|l| l.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables<'a>(lang: &'a str, requirements: &'a str) -> PromptVariables<'a> {
+        PromptVariables { lang, os: "linux", requirements }
+    }
+
+    #[test]
+    fn test_load_built_in_template() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let template = PromptTemplate::load_from(dir.path(), "/usr/bin/python3").unwrap();
+        assert_eq!(template.source, PromptTemplateSource::BuiltIn("python"));
+
+        let template = PromptTemplate::load_from(dir.path(), "pwsh").unwrap();
+        assert_eq!(template.source, PromptTemplateSource::BuiltIn("powershell"));
+
+        let template = PromptTemplate::load_from(dir.path(), "fish").unwrap();
+        assert_eq!(template.source, PromptTemplateSource::BuiltIn("generic"));
+    }
+
+    #[test]
+    fn test_user_template_overrides_built_in() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("python.txt"), "custom {{lang}}").unwrap();
+
+        let template = PromptTemplate::load_from(dir.path(), "python3").unwrap();
+
+        assert_eq!(template.source, PromptTemplateSource::File(dir.path().join("python.txt")));
+        assert_eq!(template.render(&variables("python3", "")), "custom python3");
+    }
+
+    #[test]
+    fn test_command_template_has_no_script_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = PromptTemplate::load_command_from(dir.path()).unwrap();
+        assert_eq!(template.source, PromptTemplateSource::BuiltIn("command"));
+
+        let rendered = template.render(&variables("sh", ""));
+        assert!(rendered.contains("a single command line"));
+        assert!(!rendered.contains("#!/"));
+
+        std::fs::write(dir.path().join("command.txt"), "one {{lang}} line").unwrap();
+        let template = PromptTemplate::load_command_from(dir.path()).unwrap();
+        assert_eq!(template.render(&variables("fish", "")), "one fish line");
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let template = PromptTemplate::new(PromptTemplateSource::BuiltIn("test"),
+                                           "{{lang}} on {{os}}\n{{requirements}}\n".to_string());
+
+        assert!(template.uses_requirements());
+        assert_eq!(template.render(&variables("fish", "- be nice")), "fish on linux\n- be nice");
+    }
+
+    #[test]
+    fn test_generic_template_matches_previous_prompt() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = PromptTemplate::load_from(dir.path(), "fish").unwrap();
+
+        let rendered = template.render(&variables("fish", ""));
+
+        assert!(rendered.starts_with("You are a fish script generator.\n"));
+        assert!(rendered.ends_with("never use ''' to indicate the script start and end"));
+    }
+}
@@ -0,0 +1,429 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::ProvidersConfig;
+use crate::redact::redact;
+
+/// Lock and manifest files that tell which package manager a project uses, most specific first.
+const PROJECT_PACKAGE_MANAGERS: [(&str, &str); 14] = [
+    ("Cargo.toml", "cargo"),
+    ("pnpm-lock.yaml", "pnpm"),
+    ("yarn.lock", "yarn"),
+    ("bun.lockb", "bun"),
+    ("package-lock.json", "npm"),
+    ("package.json", "npm"),
+    ("poetry.lock", "poetry"),
+    ("Pipfile", "pipenv"),
+    ("requirements.txt", "pip"),
+    ("go.mod", "go"),
+    ("Gemfile", "bundler"),
+    ("pom.xml", "maven"),
+    ("build.gradle", "gradle"),
+    ("composer.json", "composer"),
+];
+
+/// System package managers, recognized by their executable on the `PATH`.
+pub const SYSTEM_PACKAGE_MANAGERS: [&str; 13] = [
+    "apt", "dnf", "yum", "pacman", "zypper", "apk", "emerge", "nix", "brew", "port", "winget", "choco", "scoop",
+];
+
+/// At most this many entries of the working directory are listed by `@cwd`.
+const MAX_CWD_ENTRIES: usize = 100;
+
+/// Context that is computed by houston itself instead of a context file,
+/// so it is fast and the same on every shell. Used as `-c @<name>` or `${@<name>}` in context files.
+pub trait ContextProvider {
+    /// The name used to refer to the provider, e.g. `git` for `-c @git`.
+    fn name(&self) -> &'static str;
+
+    /// Describes the context. Missing tools are reported as part of the result, not as an error.
+    fn provide(&self, args: &[&str]) -> io::Result<String>;
+}
+
+/// The built-in context providers.
+pub struct ProviderRegistry {
+    providers: Vec<Box<dyn ContextProvider>>,
+}
+
+impl ProviderRegistry {
+    pub fn builtin(config: &ProvidersConfig) -> Self {
+        ProviderRegistry {
+            providers: vec![
+                Box::new(OsProvider),
+                Box::new(CwdProvider),
+                Box::new(GitProvider),
+                Box::new(PackageManagerProvider),
+                Box::new(ToolsProvider { tools: config.tools.clone() }),
+                Box::new(EnvProvider { allowlist: config.env_allowlist.clone() }),
+            ],
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.providers.iter().map(|p| p.name()).collect()
+    }
+
+    pub fn provide(&self, name: &str, args: &[&str]) -> io::Result<String> {
+        let provider = self.providers.iter().find(|p| p.name() == name).ok_or_else(|| io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Unknown context provider @{}. Available providers: @{}", name, self.names().join(", @"))))?;
+        provider.provide(args)
+    }
+}
+
+/// `@os`: operating system, distribution, version and architecture.
+pub struct OsProvider;
+
+impl ContextProvider for OsProvider {
+    fn name(&self) -> &'static str {
+        "os"
+    }
+
+    fn provide(&self, _args: &[&str]) -> io::Result<String> {
+        let mut details = vec![std::env::consts::OS.to_string(), std::env::consts::ARCH.to_string()];
+        if let Some(kernel) = kernel_version() {
+            details.push(format!("kernel {}", kernel));
+        }
+        Ok(match distribution() {
+            Some(distribution) => format!("the operating system is {} ({})", distribution, details.join(", ")),
+            None => format!("the operating system is {}", details.join(", ")),
+        })
+    }
+}
+
+/// The name and version of the Linux distribution, or the macOS / Windows version.
+pub fn distribution() -> Option<String> {
+    match std::env::consts::OS {
+        "linux" => std::fs::read_to_string("/etc/os-release").ok().and_then(|c| parse_os_release(&c)),
+        "macos" => command_output("sw_vers", &["-productVersion"]).map(|v| format!("macOS {}", v)),
+        "windows" => command_output("cmd", &["/C", "ver"]),
+        _ => None,
+    }
+}
+
+fn kernel_version() -> Option<String> {
+    match std::env::consts::OS {
+        "linux" => std::fs::read_to_string("/proc/sys/kernel/osrelease").ok().map(|v| v.trim().to_string()),
+        "windows" => None,
+        _ => command_output("uname", &["-r"]),
+    }
+}
+
+fn parse_os_release(content: &str) -> Option<String> {
+    let value = |key: &str| content.lines()
+        .find_map(|l| l.strip_prefix(key)?.strip_prefix('='))
+        .map(|v| v.trim_matches('"').to_string());
+    value("PRETTY_NAME").or_else(|| {
+        let name = value("NAME")?;
+        Some(match value("VERSION_ID") {
+            Some(version) => format!("{} {}", name, version),
+            None => name,
+        })
+    })
+}
+
+/// `@cwd`: the working directory and its entries.
+pub struct CwdProvider;
+
+impl ContextProvider for CwdProvider {
+    fn name(&self) -> &'static str {
+        "cwd"
+    }
+
+    fn provide(&self, _args: &[&str]) -> io::Result<String> {
+        let cwd = std::env::current_dir()?;
+        let mut entries = std::fs::read_dir(&cwd)?
+            .filter_map(|e| e.ok())
+            .map(|e| {
+                let suffix = if e.file_type().is_ok_and(|t| t.is_dir()) { "/" } else { "" };
+                format!("{}{}", e.file_name().to_string_lossy(), suffix)
+            })
+            .collect::<Vec<String>>();
+        entries.sort();
+
+        let mut result = format!("the current working directory is {}", cwd.display());
+        if entries.is_empty() {
+            result.push_str(" and it is empty");
+            return Ok(result);
+        }
+        let more = entries.len().saturating_sub(MAX_CWD_ENTRIES);
+        entries.truncate(MAX_CWD_ENTRIES);
+        if more > 0 {
+            entries.push(format!("... and {} more", more));
+        }
+        result.push_str(&format!("\nit contains:\n```\n{}\n```", entries.join("\n")));
+        Ok(result)
+    }
+}
+
+/// `@git`: branch, status and remotes of the repository in the working directory.
+pub struct GitProvider;
+
+impl ContextProvider for GitProvider {
+    fn name(&self) -> &'static str {
+        "git"
+    }
+
+    fn provide(&self, _args: &[&str]) -> io::Result<String> {
+        if find_on_path("git").is_none() {
+            return Ok("git is not installed".to_string());
+        }
+        Ok(git_context(&std::env::current_dir()?))
+    }
+}
+
+fn git_context(dir: &Path) -> String {
+    let dir = dir.to_string_lossy();
+    let git = |args: &[&str]| command_output("git", &[&["-C", dir.as_ref()], args].concat());
+    if git(&["rev-parse", "--is-inside-work-tree"]).as_deref() != Some("true") {
+        return "the current directory is not inside a git repository".to_string();
+    }
+
+    // `rev-parse --abbrev-ref HEAD` fails in a repository without commits, `symbolic-ref` only when detached
+    let mut lines = match (git(&["symbolic-ref", "--short", "HEAD"]), git(&["rev-parse", "--short", "HEAD"])) {
+        (Some(branch), Some(_)) => vec![format!("the current git branch is {}", branch)],
+        (Some(branch), None) => vec![format!("the current git branch is {}, which has no commits yet", branch)],
+        (None, Some(commit)) => vec![format!("the git HEAD is detached at {}", commit)],
+        (None, None) => vec![],
+    };
+    match git(&["status", "--short"]) {
+        Some(status) => lines.push(format!("the git status is:\n```\n{}\n```", status)),
+        None => lines.push("the git working tree is clean".to_string()),
+    }
+    let remotes = git(&["remote", "-v"]).map(|r| parse_remotes(&r)).unwrap_or_default();
+    if remotes.is_empty() {
+        lines.push("the git repository has no remotes".to_string());
+    } else {
+        lines.push(format!("the git remotes are: {}", redact(&remotes.join(", "))));
+    }
+    lines.join("\n")
+}
+
+/// `origin  git@host:x.git (fetch)` lines to `origin (git@host:x.git)`, once per remote.
+fn parse_remotes(output: &str) -> Vec<String> {
+    let mut remotes: Vec<String> = Vec::new();
+    for line in output.lines() {
+        let mut parts = line.split_whitespace();
+        if let (Some(name), Some(url)) = (parts.next(), parts.next()) {
+            let remote = format!("{} ({})", name, url);
+            if !remotes.contains(&remote) {
+                remotes.push(remote);
+            }
+        }
+    }
+    remotes
+}
+
+/// `@pkg`: the package manager of the project in the working directory and the system package managers.
+pub struct PackageManagerProvider;
+
+impl ContextProvider for PackageManagerProvider {
+    fn name(&self) -> &'static str {
+        "pkg"
+    }
+
+    fn provide(&self, _args: &[&str]) -> io::Result<String> {
+        let mut lines = Vec::new();
+        if let Some((file, manager)) = project_package_manager(&std::env::current_dir()?) {
+            lines.push(format!("the project in the current directory uses {} ({})", manager, file));
+        }
+        let system = system_package_managers();
+        if system.is_empty() {
+            lines.push("no system package manager was found".to_string());
+        } else {
+            lines.push(format!("the available system package managers are: {}", system.join(", ")));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+fn project_package_manager(dir: &Path) -> Option<(&'static str, &'static str)> {
+    PROJECT_PACKAGE_MANAGERS.iter().find(|(file, _)| dir.join(file).exists()).copied()
+}
+
+/// The system package managers that are installed.
+pub fn system_package_managers() -> Vec<&'static str> {
+    SYSTEM_PACKAGE_MANAGERS.iter().copied().filter(|m| find_on_path(m).is_some()).collect()
+}
+
+/// `@tools`: the versions of the configured tools (or the tools given as arguments) on the `PATH`.
+pub struct ToolsProvider {
+    tools: Vec<String>,
+}
+
+impl ContextProvider for ToolsProvider {
+    fn name(&self) -> &'static str {
+        "tools"
+    }
+
+    fn provide(&self, args: &[&str]) -> io::Result<String> {
+        let tools = if args.is_empty() {
+            self.tools.iter().map(|t| t.as_str()).collect::<Vec<&str>>()
+        } else {
+            args.to_vec()
+        };
+
+        let mut installed = Vec::new();
+        let mut missing = Vec::new();
+        for tool in tools {
+            if find_on_path(tool).is_none() {
+                missing.push(tool);
+                continue;
+            }
+            let version = tool_version(tool).unwrap_or_else(|| "unknown version".to_string());
+            installed.push(format!("{}: {}", tool, version));
+        }
+
+        let mut lines = Vec::new();
+        if !installed.is_empty() {
+            lines.push(format!("the installed tools are:\n{}", installed.join("\n")));
+        }
+        if !missing.is_empty() {
+            lines.push(format!("these tools are not installed: {}", missing.join(", ")));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// The first line of `<tool> --version`, or of the tool's own version command.
+pub fn tool_version(tool: &str) -> Option<String> {
+    let args: &[&str] = match tool {
+        "go" => &["version"],
+        "kubectl" => &["version", "--client"],
+        "java" => &["-version"],
+        _ => &["--version"],
+    };
+    let output = Command::new(tool).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // some tools (e.g. older java) print their version to stderr
+    let text = [output.stdout, output.stderr].iter()
+        .map(|o| String::from_utf8_lossy(o).to_string())
+        .find(|o| !o.trim().is_empty())?;
+    text.lines().map(|l| l.trim()).find(|l| !l.is_empty()).map(|l| l.to_string())
+}
+
+/// `@env`: the values of the allowlisted environment variables (or of the given ones, as far as they are allowlisted),
+/// with secrets redacted.
+pub struct EnvProvider {
+    allowlist: Vec<String>,
+}
+
+impl ContextProvider for EnvProvider {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn provide(&self, args: &[&str]) -> io::Result<String> {
+        let (names, rejected): (Vec<&str>, Vec<&str>) = if args.is_empty() {
+            (self.allowlist.iter().map(|n| n.as_str()).collect(), vec![])
+        } else {
+            args.iter().partition(|name| self.allowlist.iter().any(|allowed| allowed == *name))
+        };
+        let variables = names.iter()
+            .filter_map(|name| std::env::var(name).ok().map(|value| redact(&format!("{}={}", name, value))))
+            .collect::<Vec<String>>();
+        let mut result = if variables.is_empty() {
+            "none of the environment variables are set".to_string()
+        } else {
+            format!("the environment variables are:\n```\n{}\n```", variables.join("\n"))
+        };
+        if !rejected.is_empty() {
+            result.push_str(&format!("\nthe values of {} are not shown, since they are not in providers.envAllowlist",
+                                     rejected.join(", ")));
+        }
+        Ok(result)
+    }
+}
+
+/// Finds an executable on the `PATH` (with the `PATHEXT` extensions on Windows).
+pub fn find_on_path(name: &str) -> Option<PathBuf> {
+    let extensions = if cfg!(windows) {
+        std::env::var("PATHEXT").unwrap_or_else(|_| ".EXE;.CMD;.BAT".to_string())
+            .split(';')
+            .map(|e| e.to_string())
+            .collect::<Vec<String>>()
+    } else {
+        vec![String::new()]
+    };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .flat_map(|dir| extensions.iter().map(move |e| dir.join(format!("{}{}", name, e))))
+        .find(|path| path.is_file())
+}
+
+/// The trimmed stdout of a successful command, if it is not empty.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim_end().to_string()).filter(|o| !o.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_only_reports_allowlisted_variables() {
+        std::env::set_var("HU_TEST_ENV_ALLOWED", "visible-value");
+        std::env::set_var("HU_TEST_ENV_SECRET", "secret-value");
+        let provider = EnvProvider { allowlist: vec!["HU_TEST_ENV_ALLOWED".to_string()] };
+
+        let output = provider.provide(&["HU_TEST_ENV_ALLOWED", "HU_TEST_ENV_SECRET"]).unwrap();
+
+        assert!(output.contains("HU_TEST_ENV_ALLOWED=visible-value"), "{}", output);
+        assert!(!output.contains("secret-value"), "{}", output);
+        assert!(output.contains("the values of HU_TEST_ENV_SECRET are not shown"), "{}", output);
+        assert_eq!(provider.provide(&["HU_TEST_ENV_SECRET"]).unwrap(), "none of the environment variables are set\n\
+            the values of HU_TEST_ENV_SECRET are not shown, since they are not in providers.envAllowlist");
+    }
+
+    #[test]
+    fn test_git_context_in_a_fresh_repository() {
+        if find_on_path("git").is_none() {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(git_context(dir.path()), "the current directory is not inside a git repository");
+
+        command_output("git", &["init", "--quiet", "--initial-branch", "trunk", &dir.path().to_string_lossy()]);
+        std::fs::write(dir.path().join("notes.txt"), "x").unwrap();
+
+        assert_eq!(git_context(dir.path()), "the current git branch is trunk, which has no commits yet\n\
+            the git status is:\n```\n?? notes.txt\n```\nthe git repository has no remotes");
+    }
+
+    #[test]
+    fn test_parse_os_release() {
+        assert_eq!(parse_os_release("NAME=\"Ubuntu\"\nPRETTY_NAME=\"Ubuntu 22.04.3 LTS\"\n"),
+                   Some("Ubuntu 22.04.3 LTS".to_string()));
+        assert_eq!(parse_os_release("NAME=\"Alpine Linux\"\nVERSION_ID=3.19.0\n"),
+                   Some("Alpine Linux 3.19.0".to_string()));
+        assert_eq!(parse_os_release(""), None);
+    }
+
+    #[test]
+    fn test_parse_remotes() {
+        let output = "origin\tgit@github.com:a/b.git (fetch)\norigin\tgit@github.com:a/b.git (push)\n";
+        assert_eq!(parse_remotes(output), vec!["origin (git@github.com:a/b.git)"]);
+    }
+
+    #[test]
+    fn test_project_package_manager() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(project_package_manager(dir.path()), None);
+        std::fs::write(dir.path().join("package.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("yarn.lock"), "").unwrap();
+        assert_eq!(project_package_manager(dir.path()), Some(("yarn.lock", "yarn")));
+    }
+
+    #[test]
+    fn test_unknown_provider() {
+        let registry = ProviderRegistry::builtin(&ProvidersConfig::default());
+        let err = registry.provide("nope", &[]).unwrap_err();
+        assert!(err.to_string().contains("@git"), "{}", err);
+    }
+}
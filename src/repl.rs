@@ -0,0 +1,234 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::RunMode;
+use crate::context::ContextCall;
+use crate::generator::Turn;
+use crate::interpreter::Interpreter;
+use crate::prompt::lang_file_stem;
+
+/// How many earlier instructions (with their scripts) are sent along with a new one.
+pub const MAX_REMEMBERED_TURNS: usize = 10;
+
+/// A line typed into the interactive session.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplInput {
+    Empty,
+    Instruction(String),
+    Command(ReplCommand),
+}
+
+/// The `:` commands of the interactive session. Commands without an argument show the current value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplCommand {
+    /// `:ctx +git -docker`
+    Context(Vec<ContextChange>),
+    /// `:shell fish`
+    Shell(Option<String>),
+    /// `:model gpt-4o`
+    Model(Option<String>),
+    /// `:mode dry`
+    Mode(Option<RunMode>),
+    /// `:forget`, clears the conversation
+    Forget,
+    Help,
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContextChange {
+    /// A context call like `git` or `git:main`, or a provider like `@docker`.
+    Add(String),
+    /// The name of a context.
+    Remove(String),
+}
+
+pub const REPL_HELP: &str = "\
+Type an instruction to generate a script for it. Later instructions can refer to earlier ones.
+  :ctx                  show the selected contexts
+  :ctx +name -name      add or remove contexts (context files, or providers like @git)
+  :shell [name]         show or change the shell
+  :model [name]         show or change the model
+  :mode [ask|dry|force] show or change the run mode
+  :forget               forget the earlier instructions
+  :help                 show this help
+  :quit                 leave (or press Ctrl-D)";
+
+/// Parses a line of the interactive session.
+pub fn parse_input(line: &str) -> Result<ReplInput, String> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(ReplInput::Empty);
+    }
+    let Some(command) = line.strip_prefix(':') else {
+        return Ok(ReplInput::Instruction(line.to_string()));
+    };
+    let (name, argument) = match command.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, Some(argument.trim()).filter(|a| !a.is_empty())),
+        None => (command, None),
+    };
+    let command = match name {
+        "ctx" => ReplCommand::Context(parse_context_changes(argument.unwrap_or_default())?),
+        "shell" => ReplCommand::Shell(argument.map(|a| a.to_string())),
+        "model" => ReplCommand::Model(argument.map(|a| a.to_string())),
+        "mode" => ReplCommand::Mode(argument.map(|a| serde_yaml::from_str::<RunMode>(a)
+            .map_err(|_| format!("Unknown run mode {}, expected ask, dry or force", a))).transpose()?),
+        "forget" => ReplCommand::Forget,
+        "help" | "h" | "?" => ReplCommand::Help,
+        "quit" | "q" | "exit" => ReplCommand::Quit,
+        other => return Err(format!("Unknown command :{}, type :help for a list of commands", other)),
+    };
+    Ok(ReplInput::Command(command))
+}
+
+/// Parses `+git:main -docker`. Words without a sign are arguments of the preceding context.
+fn parse_context_changes(argument: &str) -> Result<Vec<ContextChange>, String> {
+    let mut changes: Vec<ContextChange> = Vec::new();
+    for word in argument.split_whitespace() {
+        if let Some(call) = word.strip_prefix('+').filter(|c| !c.is_empty()) {
+            changes.push(ContextChange::Add(call.to_string()));
+        } else if let Some(name) = word.strip_prefix('-').filter(|n| !n.is_empty()) {
+            changes.push(ContextChange::Remove(name.to_string()));
+        } else if let Some(ContextChange::Add(call)) = changes.last_mut() {
+            call.push(' ');
+            call.push_str(word);
+        } else {
+            return Err(format!("Expected +name or -name, got {}", word));
+        }
+    }
+    Ok(changes)
+}
+
+/// The state that an interactive session keeps between instructions.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    /// The selected context calls, like `--context`.
+    pub context: Vec<String>,
+    /// The earlier instructions and their scripts, oldest first.
+    pub turns: Vec<Turn>,
+}
+
+impl Session {
+    pub fn new(context: Vec<String>) -> Self {
+        Session { context, turns: vec![] }
+    }
+
+    /// Adds and removes contexts. Adding a context that is already selected replaces its arguments.
+    pub fn change_context(&mut self, changes: &[ContextChange]) {
+        for change in changes {
+            let name = match change {
+                ContextChange::Add(call) | ContextChange::Remove(call) => ContextCall::parse(call).name,
+            };
+            self.context.retain(|call| ContextCall::parse(call).name != name);
+            if let ContextChange::Add(call) = change {
+                self.context.push(call.clone());
+            }
+        }
+    }
+
+    /// Remembers an instruction and its script, forgetting the oldest ones beyond [MAX_REMEMBERED_TURNS].
+    pub fn remember(&mut self, instruction: String, script: String) {
+        self.turns.push(Turn { instruction, script });
+        let excess = self.turns.len().saturating_sub(MAX_REMEMBERED_TURNS);
+        self.turns.drain(..excess);
+    }
+}
+
+/// The script, changed so that it writes its final working directory to `path` when it exits,
+/// also when it exits early. Scripts for interpreters that are not shells are returned as they are.
+///
+/// Shells use an `EXIT` trap. A script that sets its own `EXIT` trap replaces it, so its directory is then
+/// only written if it runs to its end (and not if it calls `exit`).
+pub fn with_cwd_tracking(script: &str, interpreter: &Interpreter, path: &Path) -> String {
+    let path = path.display().to_string();
+    let (hook, footer) = match lang_file_stem(interpreter.program()).as_str() {
+        "bash" | "sh" | "dash" | "zsh" | "ksh" => (
+            format!("__hu_cwd_file='{}'\ntrap 'pwd > \"$__hu_cwd_file\"' EXIT", path.replace('\'', "'\\''")),
+            // in case the script replaced the trap; the exit code of the script is kept
+            "__hu_status=$?\npwd > \"$__hu_cwd_file\"\nexit $__hu_status\n".to_string(),
+        ),
+        "fish" => (format!("function __hu_cwd --on-event fish_exit\n    pwd > '{}'\nend", path.replace('\'', "\\'")), String::new()),
+        "pwsh" | "powershell" => return format!("try {{\n{}\n}} finally {{\n    (Get-Location).Path | Set-Content -Path '{}'\n}}\n",
+                                                script.trim_end(), path.replace('\'', "''")),
+        _ => return script.to_string(),
+    };
+    // the hook goes after the shebang, the script itself is unchanged
+    let script = match script.split_once('\n') {
+        Some((shebang, rest)) if shebang.starts_with("#!") => format!("{}\n{}\n{}", shebang, hook, rest),
+        _ => format!("{}\n{}", hook, script),
+    };
+    if footer.is_empty() {
+        script
+    } else {
+        format!("{}\n{}", script.trim_end(), footer)
+    }
+}
+
+/// The directory written by a script from [with_cwd_tracking].
+pub fn read_cwd(path: &Path) -> Option<PathBuf> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let dir = PathBuf::from(content.trim());
+    (!content.trim().is_empty() && dir.is_dir()).then_some(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::InterpreterRegistry;
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(parse_input("  ").unwrap(), ReplInput::Empty);
+        assert_eq!(parse_input("list files").unwrap(), ReplInput::Instruction("list files".to_string()));
+        assert_eq!(parse_input(":mode dry").unwrap(), ReplInput::Command(ReplCommand::Mode(Some(RunMode::Dry))));
+        assert_eq!(parse_input(":model").unwrap(), ReplInput::Command(ReplCommand::Model(None)));
+        assert_eq!(parse_input(":ctx +git:main dev -docker").unwrap(), ReplInput::Command(ReplCommand::Context(vec![
+            ContextChange::Add("git:main dev".to_string()),
+            ContextChange::Remove("docker".to_string()),
+        ])));
+        assert!(parse_input(":mode fast").unwrap_err().contains("ask, dry or force"));
+        assert!(parse_input(":ctx git").is_err());
+        assert!(parse_input(":cd /tmp").unwrap_err().contains(":help"));
+    }
+
+    #[test]
+    fn test_session_context_and_memory() {
+        let mut session = Session::new(vec!["git:main".to_string(), "docker".to_string()]);
+        session.change_context(&[
+            ContextChange::Add("git:dev".to_string()),
+            ContextChange::Remove("docker".to_string()),
+            ContextChange::Add("@k8s".to_string()),
+        ]);
+        assert_eq!(session.context, vec!["git:dev", "@k8s"]);
+
+        for i in 0..MAX_REMEMBERED_TURNS + 2 {
+            session.remember(format!("step {}", i), format!("echo {}", i));
+        }
+        assert_eq!(session.turns.len(), MAX_REMEMBERED_TURNS);
+        assert_eq!(session.turns[0].instruction, "step 2");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cwd_tracking_follows_cd_and_keeps_the_exit_code() {
+        use crate::runner::{ScriptRunner, SimpleScriptRunner};
+        let dir = tempfile::tempdir().unwrap();
+        let cwd_file = dir.path().join("cwd");
+        let sh = InterpreterRegistry::default().resolve("sh");
+
+        let script = with_cwd_tracking(&format!("#!/bin/sh\ncd '{}'\nexit 3", dir.path().display()), &sh, &cwd_file);
+        assert!(script.starts_with("#!/bin/sh\n__hu_cwd_file="), "{}", script);
+        let outcome = SimpleScriptRunner::with_interpreter(sh.clone()).run_script(&script, &[], None).unwrap();
+
+        assert_eq!(outcome.exit_code, Some(3));
+        assert_eq!(read_cwd(&cwd_file).unwrap().canonicalize().unwrap(), dir.path().canonicalize().unwrap());
+        // a trap of the script replaces the hook, the end of the script still writes the directory
+        let _ = std::fs::remove_file(&cwd_file);
+        let script = with_cwd_tracking(&format!("trap 'true' EXIT\ncd '{}'\nfalse", dir.path().display()), &sh, &cwd_file);
+        let outcome = SimpleScriptRunner::with_interpreter(sh).run_script(&script, &[], None).unwrap();
+        assert_eq!(outcome.exit_code, Some(1));
+        assert_eq!(read_cwd(&cwd_file).unwrap().canonicalize().unwrap(), dir.path().canonicalize().unwrap());
+
+        let python = InterpreterRegistry::default().resolve("python");
+        assert_eq!(with_cwd_tracking("print(1)", &python, &cwd_file), "print(1)");
+    }
+}
@@ -1,14 +1,32 @@
 use std::io;
-use std::process::Command;
-use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::io::{BufRead, BufReader, Read, Write};
 
+use crate::interpreter::{Interpreter, InterpreterRegistry};
 use crate::tmp_file::SharableTmpFile;
 
 
+/// How a script run ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptOutcome {
+    /// The exit code, `None` if the process was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// Everything the script wrote to stderr (which is also passed through to our own stderr),
+    /// if the runner captures it, see [SimpleScriptRunner::capturing_stderr].
+    pub stderr: String,
+}
+
+impl ScriptOutcome {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
 pub trait ScriptRunner {
     fn run_script(&self, script: &str,
                   args: &[&str],
-                  handle_stdout: Option<&mut dyn FnMut(&str)>) -> io::Result<()>;
+                  handle_stdout: Option<&mut dyn FnMut(&str)>) -> io::Result<ScriptOutcome>;
 
     fn run_script_and_get_stdout(&self, script: &str, args: &[&str]) -> io::Result<String> {
         let mut stdout = String::new();
@@ -22,37 +40,50 @@ pub trait ScriptRunner {
 }
 
 pub struct SimpleScriptRunner {
-    shell: String,
+    interpreter: Interpreter,
+    capture_stderr: bool,
 }
 
 
 impl SimpleScriptRunner {
+    /// Creates a runner for the given shell, looked up in the built-in interpreter registry.
     pub fn new(program: &str) -> Self {
+        Self::with_interpreter(InterpreterRegistry::default().resolve(program))
+    }
+
+    pub fn with_interpreter(interpreter: Interpreter) -> Self {
         SimpleScriptRunner {
-            shell: program.to_string()
+            interpreter,
+            capture_stderr: false,
         }
     }
+
+    /// Captures the stderr of the script in [ScriptOutcome::stderr] while passing it through.
+    /// Otherwise the script writes to our stderr directly, so it still sees a terminal there.
+    pub fn capturing_stderr(mut self) -> Self {
+        self.capture_stderr = true;
+        self
+    }
 }
 
 impl ScriptRunner for SimpleScriptRunner {
-    fn run_script(&self, script: &str, args: &[&str], handle_stdout: Option<&mut dyn FnMut(&str)>) -> io::Result<()> {
-        let mut command = Command::new(&self.shell);
-
-
-        let suffix = if self.shell.to_lowercase().contains("powershell") {
-            ".ps1"
-        } else {
-            ""
-        };
-
-        let tmp_file = SharableTmpFile::new(script, suffix)?;
+    fn run_script(&self, script: &str, args: &[&str], handle_stdout: Option<&mut dyn FnMut(&str)>) -> io::Result<ScriptOutcome> {
+        let tmp_file = SharableTmpFile::new(script, &self.interpreter.suffix)?;
+
+        let command_line = self.interpreter.command_line(tmp_file.path.to_str().unwrap(), args);
+        let mut command = Command::new(&command_line[0]);
+        command.args(&command_line[1..]);
+        if self.capture_stderr {
+            command.stderr(Stdio::piped());
+        }
+        if handle_stdout.is_some() {
+            command.stdout(Stdio::piped());
+        }
 
-        command.arg(tmp_file.path.to_str().unwrap());
-        command.args(args);
+        let mut child = command.spawn()?;
+        let stderr = child.stderr.take().map(tee_stderr);
 
-        let process = if let Some(handle_stdout) = handle_stdout {
-            command.stdout(std::process::Stdio::piped());
-            let mut child = command.spawn()?;
+        if let Some(handle_stdout) = handle_stdout {
             let stdout = child.stdout.take().unwrap();
             let reader = BufReader::new(stdout);
 
@@ -60,27 +91,74 @@ impl ScriptRunner for SimpleScriptRunner {
                 match line {
                     Ok(line) => handle_stdout(&line),
                     e => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::Other,
+                        return Err(io::Error::other(
                             format!("Failed to read stdout: {:?}", e),
                         ));
                     }
                 }
             }
-            child
-        } else {
-            command.spawn()?
+        }
+
+        let status = child.wait()?;
+        let stderr = match stderr {
+            Some(stderr) => stderr.join().map_err(|_| io::Error::other("Failed to read stderr"))?,
+            None => String::new(),
         };
+        Ok(ScriptOutcome {
+            exit_code: status.code(),
+            stderr,
+        })
+    }
+}
 
-        process.wait_with_output()?;
-        Ok(())
+/// Saves the script to `path` and makes it executable.
+/// If `path` has no extension, `suffix` (e.g. `.sh`) is added. Returns the path of the file.
+pub fn save_script(path: &Path, script: &str, suffix: &str) -> io::Result<PathBuf> {
+    let path = match suffix.strip_prefix('.') {
+        Some(extension) if path.extension().is_none() && !extension.is_empty() => path.with_extension(extension),
+        _ => path.to_path_buf(),
+    };
+    let mut content = script.to_string();
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    std::fs::write(&path, content)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = std::fs::metadata(&path)?.permissions();
+        // executable for everyone who may read it
+        let mode = permissions.mode();
+        permissions.set_mode(mode | ((mode & 0o444) >> 2));
+        std::fs::set_permissions(&path, permissions)?;
     }
+    Ok(path)
+}
+
+/// Passes the script's stderr through to our own stderr as it arrives (prompts without a newline included),
+/// and collects it.
+fn tee_stderr(mut stderr: impl Read + Send + 'static) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut collected = Vec::new();
+        let mut buffer = [0; 4096];
+        while let Ok(n) = stderr.read(&mut buffer) {
+            if n == 0 {
+                break;
+            }
+            let mut own_stderr = io::stderr();
+            let _ = own_stderr.write_all(&buffer[..n]);
+            let _ = own_stderr.flush();
+            collected.extend_from_slice(&buffer[..n]);
+        }
+        String::from_utf8_lossy(&collected).to_string()
+    })
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::runner::{ScriptRunner, SimpleScriptRunner};
+    use crate::runner::{save_script, ScriptRunner, SimpleScriptRunner};
 
     #[test]
     fn test_shell_script_runner_with_args() {
@@ -96,4 +174,40 @@ mod tests {
 
         assert_eq!(result.unwrap().trim(), "hello world");
     }
+
+    #[test]
+    fn test_outcome_contains_exit_code_and_stderr() {
+        if cfg!(windows) {
+            return;
+        }
+        let runner = SimpleScriptRunner::new("bash").capturing_stderr();
+
+        let outcome = runner.run_script("echo oops >&2; exit 3", &[], Some(&mut |_| {})).unwrap();
+
+        assert_eq!(outcome.exit_code, Some(3));
+        assert!(!outcome.success());
+        assert_eq!(outcome.stderr, "oops\n");
+
+        let outcome = SimpleScriptRunner::new("bash").run_script("echo oops >&2; exit 3", &[], None).unwrap();
+        assert_eq!(outcome.exit_code, Some(3));
+        assert_eq!(outcome.stderr, "");
+    }
+
+    #[test]
+    fn test_save_script_adds_extension_and_executable_bit() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = save_script(&dir.path().join("cleanup"), "echo hi", ".sh").unwrap();
+        assert_eq!(path, dir.path().join("cleanup.sh"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "echo hi\n");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_ne!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o100, 0);
+        }
+
+        let path = save_script(&dir.path().join("tool.bash"), "echo hi\n", ".sh").unwrap();
+        assert_eq!(path, dir.path().join("tool.bash"));
+    }
 }
@@ -0,0 +1,233 @@
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde_yaml::Value;
+
+use crate::config::{DEFAULT_OPEN_AI_BASE_URL, HttpConfig};
+use crate::http::{HttpClient, HttpError};
+use crate::migration::CONFIG_VERSION;
+use crate::prompt::detect_shell_version;
+use crate::provider::find_on_path;
+
+/// The shells that are offered, if they are installed.
+const CANDIDATE_SHELLS: [&str; 7] = ["bash", "zsh", "fish", "sh", "pwsh", "powershell", "nu"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedShell {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+/// The installed shells, in the order of [CANDIDATE_SHELLS].
+pub fn available_shells() -> Vec<DetectedShell> {
+    CANDIDATE_SHELLS.iter()
+        .filter(|shell| find_on_path(shell).is_some())
+        .map(|shell| DetectedShell { name: shell.to_string(), version: detect_shell_version(shell) })
+        .collect()
+}
+
+/// Where the API key is kept.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeySource {
+    /// The `OPENAI_API_KEY` environment variable.
+    Env,
+    /// A file only the user can read (`openAi.apiKeyFile`).
+    File(PathBuf),
+    /// A command that prints the key (`openAi.apiKeyCommand`).
+    Command(String),
+    /// The config file itself (`openAi.apiKey`).
+    Config(String),
+}
+
+/// What the user chose in `hu setup`.
+#[derive(Debug, Clone)]
+pub struct SetupAnswers {
+    pub shell: String,
+    pub context_shell: String,
+    pub base_url: String,
+    pub model: String,
+    pub key_source: KeySource,
+}
+
+/// The config keys for the answers, as a path and a value each.
+pub fn config_entries(answers: &SetupAnswers) -> Vec<(Vec<String>, Value)> {
+    let key = |k: &str| k.split('.').map(|s| s.to_string()).collect::<Vec<String>>();
+    let mut entries = vec![
+        (key("version"), Value::Number(CONFIG_VERSION.into())),
+        (key("defaultShell"), Value::String(answers.shell.clone())),
+        (key("defaultContextShell"), Value::String(answers.context_shell.clone())),
+        (key("openAi.model"), Value::String(answers.model.clone())),
+    ];
+    if !is_default_base_url(&answers.base_url) {
+        entries.push((key("openAi.baseUrl"), Value::String(answers.base_url.clone())));
+    }
+    match &answers.key_source {
+        KeySource::Env => {}
+        KeySource::File(path) => entries.push((key("openAi.apiKeyFile"), Value::String(path.display().to_string()))),
+        KeySource::Command(command) => entries.push((key("openAi.apiKeyCommand"), Value::String(command.clone()))),
+        KeySource::Config(api_key) => entries.push((key("openAi.apiKey"), Value::String(api_key.clone()))),
+    }
+    entries
+}
+
+/// Whether the base URL is the one of OpenAI, so that it does not need to be written to the config.
+pub fn is_default_base_url(base_url: &str) -> bool {
+    base_url.trim_end_matches('/') == DEFAULT_OPEN_AI_BASE_URL.trim_end_matches('/')
+}
+
+/// Writes the key to a file that only the user can read.
+pub fn write_api_key_file(path: &Path, api_key: &str) -> io::Result<()> {
+    create_private_file(path)?;
+    let mut file = std::fs::OpenOptions::new().write(true).truncate(true).open(path)?;
+    writeln!(file, "{}", api_key)
+}
+
+/// Creates the file (and its directory) readable only by the user, or makes an existing one private,
+/// so that a secret written to it afterwards is never readable by others. The content is kept.
+pub fn create_private_file(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?;
+    restrict_to_user(path)
+}
+
+/// Makes a file readable only by the user (a no-op on Windows).
+pub fn restrict_to_user(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Lists the models of the endpoint, which also checks the URL and the key.
+pub fn check_connection(base_url: &str, api_key: &str) -> Result<Vec<String>, HttpError> {
+    let client = HttpClient::new(&HttpConfig { timeout_seconds: 15, max_retries: 0, ..HttpConfig::default() });
+    let url = format!("{}/models", base_url.trim_end_matches('/'));
+    let authorization = format!("Bearer {}", api_key);
+    let response = client.get_json(&url, &[("Authorization", &authorization)])?;
+    let models = response.get("data").and_then(|d| d.as_array()).into_iter().flatten()
+        .filter_map(|model| model.get("id").and_then(|id| id.as_str()))
+        .map(|id| id.to_string())
+        .collect();
+    Ok(models)
+}
+
+/// A question for the starter `default.ctxt` and the line it adds if the answer is yes.
+pub struct Preference {
+    pub question: &'static str,
+    pub context: &'static str,
+}
+
+pub const PREFERENCES: [Preference; 5] = [
+    Preference {
+        question: "Do you prefer short one-liners over longer scripts?",
+        context: "I prefer short one-liners over longer scripts, if the task allows it",
+    },
+    Preference {
+        question: "Should scripts explain what they do in comments?",
+        context: "Add short comments that explain what the script does",
+    },
+    Preference {
+        question: "Should scripts ask before deleting or overwriting files?",
+        context: "Ask for confirmation before deleting or overwriting files",
+    },
+    Preference {
+        question: "Should scripts avoid sudo?",
+        context: "Don't use sudo, tell me instead when a command needs more privileges",
+    },
+    Preference {
+        question: "Do you want colorful, human friendly output?",
+        context: "Make the output of the script colorful and easy to read",
+    },
+];
+
+/// The content of a starter `default.ctxt` with the chosen preferences.
+pub fn default_context(preferences: &[&Preference]) -> String {
+    let mut lines = vec![
+        "- The current working directory is ${pwd}".to_string(),
+    ];
+    lines.extend(preferences.iter().map(|p| format!("- {}", p.context)));
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::Mapping;
+
+    use super::*;
+    use crate::config_file::validate;
+
+    fn answers(key_source: KeySource) -> SetupAnswers {
+        SetupAnswers {
+            shell: "zsh".to_string(),
+            context_shell: "bash".to_string(),
+            base_url: "http://localhost:11434/v1".to_string(),
+            model: "llama3".to_string(),
+            key_source,
+        }
+    }
+
+    fn document(entries: Vec<(Vec<String>, Value)>) -> String {
+        let mut document = Value::Mapping(Mapping::new());
+        for (path, value) in entries {
+            crate::config_file::set(&mut document, &path, value);
+        }
+        serde_yaml::to_string(&document).unwrap()
+    }
+
+    #[test]
+    fn test_config_entries_are_a_valid_config() {
+        let text = document(config_entries(&answers(KeySource::Command("pass show openai".to_string()))));
+
+        assert_eq!(validate(&text), vec![]);
+        assert!(text.contains("apiKeyCommand: pass show openai"), "{}", text);
+        assert!(text.contains("defaultShell: zsh"), "{}", text);
+        assert!(!document(config_entries(&answers(KeySource::Env))).contains("apiKey"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_api_key_file_is_private() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("houston").join("openai.key");
+
+        write_api_key_file(&path, "sk-test").unwrap();
+
+        assert_eq!(crate::api_key::read_api_key_file(&path).unwrap(), "sk-test");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_private_file_keeps_the_content() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yml");
+        std::fs::write(&path, "version: 2\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        create_private_file(&path).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "version: 2\n");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_default_context() {
+        let context = default_context(&[&PREFERENCES[0]]);
+        assert_eq!(context, "- The current working directory is ${pwd}\n\
+            - I prefer short one-liners over longer scripts, if the task allows it\n");
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt::Display;
 use std::{fmt, io};
+use crate::context::ContextCall;
+use crate::provider::ProviderRegistry;
 use crate::runner::{ScriptRunner};
 
 pub trait TemplateEvaluator {
@@ -15,11 +17,28 @@ pub enum TemplateEvaluationError {
 
 pub struct DefaultTemplateEvaluator {
     script_runner: Box<dyn ScriptRunner>,
+    providers: Option<ProviderRegistry>,
 }
 
 impl DefaultTemplateEvaluator {
     pub fn new(script_runner: Box<dyn ScriptRunner>) -> Self {
-        DefaultTemplateEvaluator { script_runner }
+        DefaultTemplateEvaluator { script_runner, providers: None }
+    }
+
+    /// Evaluates `${@name}` and `${@name:args}` with the given context providers instead of the shell.
+    pub fn with_providers(mut self, providers: ProviderRegistry) -> Self {
+        self.providers = Some(providers);
+        self
+    }
+
+    fn evaluate_command(&self, cmd: &str, args: &[&str]) -> io::Result<String> {
+        match (cmd.trim().strip_prefix('@'), &self.providers) {
+            (Some(call), Some(providers)) => {
+                let call = ContextCall::parse(call);
+                providers.provide(&call.name, &call.args_as_str_vec())
+            }
+            _ => self.script_runner.run_script_and_get_stdout(cmd, args),
+        }
     }
 }
 
@@ -54,8 +73,7 @@ impl TemplateEvaluator for DefaultTemplateEvaluator {
                     }
 
                     let cmd = &remaining_text[open_index + 2..close_index as usize];
-                    let cmd_out = self.script_runner.run_script_and_get_stdout(
-                        cmd, args).map_err(TemplateEvaluationError::IoError)?;
+                    let cmd_out = self.evaluate_command(cmd, args).map_err(TemplateEvaluationError::IoError)?;
 
                     result.push_str(&remaining_text[..open_index]);
                     result.push_str(cmd_out.trim_end());
@@ -100,6 +118,7 @@ fn find_closing_bracket(
 mod tests {
     use std::collections::HashMap;
     use crate::template::TemplateEvaluationError::{IoError, SyntaxError};
+    use crate::runner::ScriptOutcome;
     use super::*;
 
 
@@ -157,15 +176,18 @@ mod tests {
     impl ScriptRunner for MockScriptRunner {
         fn run_script(&self, script: &str,
                       _args: &[&str],
-                      _handle_stdout: Option<&mut dyn FnMut(&str)>) -> io::Result<()> {
-            self.run_script_and_get_stdout(script, _args).map(|_| ())
+                      _handle_stdout: Option<&mut dyn FnMut(&str)>) -> io::Result<ScriptOutcome> {
+            self.run_script_and_get_stdout(script, _args).map(|_| ScriptOutcome {
+                exit_code: Some(0),
+                stderr: String::new(),
+            })
         }
 
         fn run_script_and_get_stdout(&self, script: &str, _args: &[&str]) -> io::Result<String> {
-            return match self.input_output_map.get(script) {
+            match self.input_output_map.get(script) {
                 Some(value) => Ok(value.to_string()),
                 None => Err(io::Error::new(io::ErrorKind::NotFound, "not found")),
-            };
+            }
         }
     }
 
@@ -251,4 +273,15 @@ mod tests {
 
         assert!(matches!(result, Err(IoError(_))), "Expected IoError, got {:?}", result);
     }
+
+    #[test]
+    fn test_provider_calls_do_not_use_the_shell() {
+        let script_runner = Box::new(MockScriptRunner::new_single_command("echo world", "world"));
+        let evaluator = DefaultTemplateEvaluator::new(script_runner)
+            .with_providers(ProviderRegistry::builtin(&Default::default()));
+
+        let result = evaluator.evaluate("${echo world}: ${@tools:houston-missing-tool}", &[]);
+
+        assert_eq!(result.unwrap(), "world: these tools are not installed: houston-missing-tool");
+    }
 }
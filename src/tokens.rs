@@ -0,0 +1,407 @@
+use serde::{Deserialize, Serialize};
+
+use crate::context::ContextBlock;
+
+/// The tokenizer family a model belongs to.
+/// Determines how many characters a token covers on average.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenizerFamily {
+    Cl100k,
+    O200k,
+    Unknown,
+}
+
+impl TokenizerFamily {
+    pub fn for_model(model: &str) -> Self {
+        let model = model.to_lowercase();
+        if model.starts_with("gpt-4o") || model.starts_with("gpt-4.1") || model.starts_with("gpt-5")
+            || model.starts_with("o1") || model.starts_with("o3") || model.starts_with("o4") {
+            TokenizerFamily::O200k
+        } else if model.starts_with("gpt-4") || model.starts_with("gpt-3.5") || model.contains("embedding") {
+            TokenizerFamily::Cl100k
+        } else {
+            TokenizerFamily::Unknown
+        }
+    }
+
+    fn single_token_word_length(&self) -> usize {
+        match self {
+            TokenizerFamily::Cl100k => 7,
+            TokenizerFamily::O200k => 8,
+            TokenizerFamily::Unknown => 5,
+        }
+    }
+
+    fn chars_per_word_token(&self) -> f64 {
+        match self {
+            TokenizerFamily::Cl100k => 4.0,
+            TokenizerFamily::O200k => 4.4,
+            TokenizerFamily::Unknown => 3.5,
+        }
+    }
+}
+
+/// Returns the size of the context window (prompt + completion) of the given model in tokens.
+pub fn context_window(model: &str) -> usize {
+    let model = model.to_lowercase();
+    if model.starts_with("gpt-4.1") {
+        1_047_576
+    } else if model.starts_with("gpt-4o") || model.starts_with("gpt-4-turbo")
+        || model.starts_with("gpt-4-1106") || model.starts_with("gpt-4-0125") || model.starts_with("o1") {
+        128_000
+    } else if model.starts_with("o3") || model.starts_with("o4") || model.starts_with("gpt-5") {
+        200_000
+    } else if model.starts_with("gpt-4-32k") {
+        32_768
+    } else if model.starts_with("gpt-4") {
+        8_192
+    } else if model.starts_with("gpt-3.5-turbo") {
+        16_385
+    } else {
+        4_096
+    }
+}
+
+/// Estimates token counts without shipping the actual BPE vocabularies.
+/// The text is split the way the tokenizers pre-split it (words, numbers, whitespace, punctuation)
+/// and each piece is weighted according to the tokenizer family.
+/// The counts are approximate and can be off in either direction, see [TokenEstimator::conservative].
+#[derive(Debug, Clone, Copy)]
+pub struct TokenEstimator {
+    family: TokenizerFamily,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PieceKind {
+    Word,
+    Number,
+    Whitespace,
+    Punctuation,
+    Other,
+}
+
+impl TokenEstimator {
+    pub fn for_model(model: &str) -> Self {
+        TokenEstimator { family: TokenizerFamily::for_model(model) }
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        self.pieces(text).iter().map(|(_, tokens)| tokens).sum()
+    }
+
+    /// Shrinks a budget of real tokens by a safety margin, so that estimated counts that are too low
+    /// do not overflow it.
+    pub fn conservative(&self, budget: usize) -> usize {
+        budget - budget / ESTIMATE_SAFETY_MARGIN
+    }
+
+    /// Splits the text into pre-tokenizer pieces together with their estimated token count.
+    fn pieces<'a>(&self, text: &'a str) -> Vec<(&'a str, usize)> {
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        let mut current: Option<PieceKind> = None;
+
+        for (i, c) in text.char_indices() {
+            let kind = piece_kind(c);
+            // a single leading space belongs to the following word, as in the real tokenizers
+            let joins_word = kind == PieceKind::Word && current == Some(PieceKind::Whitespace)
+                && &text[start..i] == " ";
+            match current {
+                Some(k) if k == kind && kind != PieceKind::Other => {}
+                Some(_) if joins_word => current = Some(PieceKind::Word),
+                Some(k) => {
+                    pieces.push((&text[start..i], self.piece_tokens(&text[start..i], k)));
+                    start = i;
+                    current = Some(kind);
+                }
+                None => current = Some(kind),
+            }
+        }
+        if let Some(k) = current {
+            pieces.push((&text[start..], self.piece_tokens(&text[start..], k)));
+        }
+        pieces
+    }
+
+    fn piece_tokens(&self, piece: &str, kind: PieceKind) -> usize {
+        let len = piece.chars().count();
+        match kind {
+            PieceKind::Word => {
+                // common words are single tokens, rare and long ones get split into chunks
+                let letters = piece.trim_start().chars().count();
+                if letters <= self.family.single_token_word_length() {
+                    1
+                } else {
+                    (letters as f64 / self.family.chars_per_word_token()).ceil() as usize
+                }
+            }
+            PieceKind::Number => len.div_ceil(3),
+            PieceKind::Whitespace => piece.matches('\n').count().max(1),
+            PieceKind::Punctuation => len.div_ceil(2),
+            PieceKind::Other => 1,
+        }
+    }
+}
+
+fn piece_kind(c: char) -> PieceKind {
+    if c.is_ascii_alphabetic() {
+        PieceKind::Word
+    } else if c.is_ascii_digit() {
+        PieceKind::Number
+    } else if c.is_whitespace() {
+        PieceKind::Whitespace
+    } else if c.is_ascii_punctuation() {
+        PieceKind::Punctuation
+    } else {
+        PieceKind::Other
+    }
+}
+
+/// What to do when the context blocks do not fit into the budget.
+/// Blocks with the lowest priority are shortened first.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum TruncationStrategy {
+    /// Keep the beginning of a block.
+    Head,
+    /// Keep the end of a block.
+    Tail,
+    /// Keep beginning and end of a block and elide the middle.
+    Middle,
+    /// Remove whole blocks, starting with the lowest priority.
+    DropLowestPriority,
+}
+
+/// Token count of a context block before and after applying the budget.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextTokenUsage {
+    pub name: String,
+    pub original_tokens: usize,
+    pub tokens: usize,
+}
+
+impl ContextTokenUsage {
+    pub fn describe(&self) -> String {
+        if self.tokens == 0 && self.original_tokens > 0 {
+            format!("{}: {} tokens (dropped)", self.name, self.original_tokens)
+        } else if self.tokens < self.original_tokens {
+            format!("{}: {} tokens (truncated from {})", self.name, self.tokens, self.original_tokens)
+        } else {
+            format!("{}: {} tokens", self.name, self.tokens)
+        }
+    }
+}
+
+/// Shortens the given blocks so that their total token count does not exceed `budget`.
+/// Returns the remaining blocks (in their original order) and the token usage of every block.
+pub fn apply_budget(blocks: Vec<ContextBlock>, budget: usize, strategy: TruncationStrategy,
+                    estimator: &TokenEstimator) -> (Vec<ContextBlock>, Vec<ContextTokenUsage>) {
+    let counts = blocks.iter().map(|b| estimator.count(&b.content)).collect::<Vec<usize>>();
+    let total: usize = counts.iter().sum();
+    let mut overflow = total.saturating_sub(budget);
+
+    // lowest priority first, later blocks before earlier ones on equal priority
+    let mut order = (0..blocks.len()).collect::<Vec<usize>>();
+    order.sort_by(|a, b| blocks[*a].priority.cmp(&blocks[*b].priority).then(b.cmp(a)));
+
+    let mut contents = blocks.iter().map(|b| Some(b.content.clone())).collect::<Vec<Option<String>>>();
+    let mut final_counts = counts.clone();
+    let marker_tokens = estimator.count(&format!("\n{}\n", ELISION_MARKER));
+
+    for i in order {
+        if overflow == 0 {
+            break;
+        }
+        let keep = if strategy == TruncationStrategy::DropLowestPriority {
+            0
+        } else {
+            counts[i].saturating_sub(overflow + marker_tokens)
+        };
+
+        if keep == 0 {
+            contents[i] = None;
+            final_counts[i] = 0;
+            overflow = overflow.saturating_sub(counts[i]);
+        } else {
+            let truncated = truncate(&blocks[i].content, keep, strategy, estimator);
+            final_counts[i] = estimator.count(&truncated);
+            // the estimate of the truncated text is not always smaller than the one of the original
+            overflow = overflow.saturating_sub(counts[i].saturating_sub(final_counts[i]));
+            contents[i] = Some(truncated);
+        }
+    }
+
+    let usage = blocks.iter().zip(counts.iter().zip(final_counts.iter()))
+        .map(|(b, (original, tokens))| ContextTokenUsage {
+            name: b.name.clone(),
+            original_tokens: *original,
+            tokens: *tokens,
+        })
+        .collect();
+
+    let remaining = blocks.into_iter().zip(contents)
+        .filter_map(|(block, content)| content.map(|content| ContextBlock { content, ..block }))
+        .collect();
+
+    (remaining, usage)
+}
+
+const ELISION_MARKER: &str = "[...]";
+
+/// One in this many tokens of a budget is kept free for estimation errors.
+const ESTIMATE_SAFETY_MARGIN: usize = 10;
+
+/// Cuts `text` down to roughly `max_tokens` tokens, keeping the part selected by `strategy`.
+pub fn truncate(text: &str, max_tokens: usize, strategy: TruncationStrategy, estimator: &TokenEstimator) -> String {
+    let pieces = estimator.pieces(text);
+    if pieces.iter().map(|(_, t)| t).sum::<usize>() <= max_tokens {
+        return text.to_string();
+    }
+
+    let take = |iter: &mut dyn Iterator<Item=&(&str, usize)>, limit: usize| -> Vec<String> {
+        let mut used = 0;
+        let mut taken = Vec::new();
+        for (piece, tokens) in iter {
+            if used + tokens > limit {
+                break;
+            }
+            used += tokens;
+            taken.push(piece.to_string());
+        }
+        taken
+    };
+
+    match strategy {
+        TruncationStrategy::Head => {
+            format!("{}\n{}", take(&mut pieces.iter(), max_tokens).concat(), ELISION_MARKER)
+        }
+        TruncationStrategy::Tail => {
+            let mut tail = take(&mut pieces.iter().rev(), max_tokens);
+            tail.reverse();
+            format!("{}\n{}", ELISION_MARKER, tail.concat())
+        }
+        TruncationStrategy::Middle => {
+            let head = take(&mut pieces.iter(), max_tokens / 2);
+            let mut tail = take(&mut pieces.iter().rev(), max_tokens - max_tokens / 2);
+            tail.reverse();
+            format!("{}\n{}\n{}", head.concat(), ELISION_MARKER, tail.concat())
+        }
+        TruncationStrategy::DropLowestPriority => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(name: &str, content: &str, priority: u8) -> ContextBlock {
+        ContextBlock {
+            name: name.to_string(),
+            content: content.to_string(),
+            priority,
+        }
+    }
+
+    #[test]
+    fn test_tokenizer_family_for_model() {
+        assert_eq!(TokenizerFamily::for_model("gpt-4"), TokenizerFamily::Cl100k);
+        assert_eq!(TokenizerFamily::for_model("gpt-3.5-turbo"), TokenizerFamily::Cl100k);
+        assert_eq!(TokenizerFamily::for_model("gpt-4o-mini"), TokenizerFamily::O200k);
+        assert_eq!(TokenizerFamily::for_model("llama3"), TokenizerFamily::Unknown);
+    }
+
+    #[test]
+    fn test_context_window() {
+        assert_eq!(context_window("gpt-4"), 8_192);
+        assert_eq!(context_window("gpt-4-32k-0613"), 32_768);
+        assert_eq!(context_window("gpt-4o"), 128_000);
+    }
+
+    #[test]
+    fn test_count_is_close_to_real_tokenizer() {
+        let estimator = TokenEstimator::for_model("gpt-4");
+        // cl100k_base encodes this sentence as 10 tokens
+        let count = estimator.count("The quick brown fox jumps over the lazy dog.");
+        assert!((9..=12).contains(&count), "count was {}", count);
+        assert_eq!(estimator.count(""), 0);
+    }
+
+    #[test]
+    fn test_truncate_strategies() {
+        let estimator = TokenEstimator::for_model("gpt-4");
+        let text = "one two three four five six seven eight nine ten";
+
+        let head = truncate(text, 3, TruncationStrategy::Head, &estimator);
+        assert_eq!(head, "one two three\n[...]");
+
+        let tail = truncate(text, 3, TruncationStrategy::Tail, &estimator);
+        assert_eq!(tail, "[...]\n eight nine ten");
+
+        let middle = truncate(text, 4, TruncationStrategy::Middle, &estimator);
+        assert_eq!(middle, "one two\n[...]\n nine ten");
+
+        assert_eq!(truncate(text, 100, TruncationStrategy::Head, &estimator), text);
+    }
+
+    #[test]
+    fn test_apply_budget_within_budget_keeps_everything() {
+        let estimator = TokenEstimator::for_model("gpt-4");
+        let blocks = vec![block("a", "hello world", 1), block("b", "foo bar", 2)];
+
+        let (remaining, usage) = apply_budget(blocks, 100, TruncationStrategy::Head, &estimator);
+
+        assert_eq!(remaining.len(), 2);
+        assert!(usage.iter().all(|u| u.tokens == u.original_tokens));
+    }
+
+    #[test]
+    fn test_apply_budget_drops_lowest_priority() {
+        let estimator = TokenEstimator::for_model("gpt-4");
+        let long = "word ".repeat(50);
+        let blocks = vec![
+            block("os", "linux", 10),
+            block("default", &long, 1),
+            block("git", &long, 5),
+        ];
+
+        let (remaining, usage) = apply_budget(blocks, 60, TruncationStrategy::DropLowestPriority, &estimator);
+
+        let names = remaining.iter().map(|b| b.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, vec!["os", "git"]);
+        assert_eq!(usage[1].tokens, 0);
+        assert!(usage[1].describe().contains("dropped"));
+    }
+
+    #[test]
+    fn test_apply_budget_truncates_lowest_priority_first() {
+        let estimator = TokenEstimator::for_model("gpt-4");
+        let long = "word ".repeat(50);
+        let blocks = vec![block("high", &long, 5), block("low", &long, 1)];
+
+        let (remaining, usage) = apply_budget(blocks, 80, TruncationStrategy::Head, &estimator);
+
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(usage[0].tokens, usage[0].original_tokens);
+        assert!(usage[1].tokens < usage[1].original_tokens);
+        assert!(usage.iter().map(|u| u.tokens).sum::<usize>() <= 80);
+    }
+
+    #[test]
+    fn test_apply_budget_with_a_block_just_above_the_budget() {
+        let estimator = TokenEstimator::for_model("gpt-4");
+        for content in ["word ".repeat(20), "a.b.".repeat(20), "12345 ".repeat(20), "ö".repeat(20)] {
+            let budget = estimator.count(&content) - 1;
+            for strategy in [TruncationStrategy::Head, TruncationStrategy::Tail, TruncationStrategy::Middle] {
+                let (_, usage) = apply_budget(vec![block("a", &content, 1)], budget, strategy, &estimator);
+                assert!(usage[0].tokens <= budget, "{:?} of {:?}: {:?}", strategy, content, usage[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_conservative_budget() {
+        let estimator = TokenEstimator::for_model("gpt-4");
+        assert_eq!(estimator.conservative(8_000), 7_200);
+        assert_eq!(estimator.conservative(5), 5);
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::get_houston_dir;
+
+const LEDGER_FILE_NAME: &str = "usage.jsonl";
+
+/// Tokens consumed by a single API request.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Price of a model in USD per one million tokens.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPrice {
+    pub prompt_per_million: f64,
+    pub completion_per_million: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt_per_million
+            + usage.completion_tokens as f64 * self.completion_per_million) / 1_000_000.0
+    }
+}
+
+pub fn default_price_table() -> BTreeMap<String, ModelPrice> {
+    let prices = [
+        ("gpt-4", 30.0, 60.0),
+        ("gpt-4-32k", 60.0, 120.0),
+        ("gpt-4-turbo", 10.0, 30.0),
+        ("gpt-4o", 2.5, 10.0),
+        ("gpt-4o-mini", 0.15, 0.6),
+        ("gpt-3.5-turbo", 0.5, 1.5),
+    ];
+    prices.iter()
+        .map(|(model, prompt, completion)| (model.to_string(), ModelPrice {
+            prompt_per_million: *prompt,
+            completion_per_million: *completion,
+        }))
+        .collect()
+}
+
+/// Finds the price of a model. Dated model versions (e.g. `gpt-4o-2024-08-06`) use the price of
+/// the longest matching model name in the table.
+pub fn find_price<'a>(prices: &'a BTreeMap<String, ModelPrice>, model: &str) -> Option<&'a ModelPrice> {
+    prices.iter()
+        .filter(|(name, _)| model == name.as_str() || model.starts_with(&format!("{}-", name)))
+        .max_by_key(|(name, _)| name.len())
+        .map(|(_, price)| price)
+}
+
+/// One entry in the usage ledger.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UsageRecord {
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    pub model: String,
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+    /// Estimated cost in USD, if the model's price is known.
+    pub cost: Option<f64>,
+    pub contexts: Vec<String>,
+}
+
+impl UsageRecord {
+    pub fn new(model: &str, usage: TokenUsage, prices: &BTreeMap<String, ModelPrice>, contexts: Vec<String>) -> Self {
+        UsageRecord {
+            timestamp: now(),
+            model: model.to_string(),
+            usage,
+            cost: find_price(prices, model).map(|p| p.cost(&usage)),
+            contexts,
+        }
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Append-only log of all API requests, stored as JSON lines in the houston dir.
+pub struct UsageLedger {
+    path: PathBuf,
+}
+
+impl UsageLedger {
+    pub fn new(path: PathBuf) -> Self {
+        UsageLedger { path }
+    }
+
+    pub fn default_location() -> Self {
+        UsageLedger::new(get_houston_dir().join(LEDGER_FILE_NAME))
+    }
+
+    pub fn append(&self, record: &UsageRecord) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let line = serde_json::to_string(record).map_err(io::Error::other)?;
+        writeln!(file, "{}", line)
+    }
+
+    /// Reads all records. Lines that cannot be parsed are skipped.
+    pub fn read(&self) -> io::Result<Vec<UsageRecord>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let file = std::fs::File::open(&self.path)?;
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Ok(record) = serde_json::from_str(&line?) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Parses durations like `30m`, `12h`, `7d` or `2w` into seconds. The unit is required.
+pub fn parse_duration(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    let (amount, unit) = input.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| format!("Invalid duration: {}", input))?;
+    let unit_seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "" => return Err(format!("Missing duration unit in '{}', e.g. '{}d' for days or '{}h' for hours", input, amount, amount)),
+        _ => return Err(format!("Invalid duration unit '{}', expected one of s, m, h, d, w", unit)),
+    };
+    amount.checked_mul(unit_seconds).ok_or_else(|| format!("Invalid duration: {}", input))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UsageGrouping {
+    Model,
+    Context,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageSummary {
+    pub invocations: u64,
+    pub usage: TokenUsage,
+    pub cost: f64,
+    /// Number of invocations whose cost is unknown because the model has no price.
+    pub unpriced_invocations: u64,
+}
+
+impl UsageSummary {
+    fn add(&mut self, record: &UsageRecord) {
+        self.invocations += 1;
+        self.usage.prompt_tokens += record.usage.prompt_tokens;
+        self.usage.completion_tokens += record.usage.completion_tokens;
+        match record.cost {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced_invocations += 1,
+        }
+    }
+}
+
+/// Sums up all records since the given timestamp. Without grouping, everything ends up in a single `total` group.
+/// When grouping by context, an invocation counts towards every context it used.
+pub fn summarize(records: &[UsageRecord], since: u64, grouping: Option<UsageGrouping>) -> BTreeMap<String, UsageSummary> {
+    let mut groups: BTreeMap<String, UsageSummary> = BTreeMap::new();
+    for record in records.iter().filter(|r| r.timestamp >= since) {
+        let keys = match grouping {
+            None => vec!["total".to_string()],
+            Some(UsageGrouping::Model) => vec![record.model.clone()],
+            Some(UsageGrouping::Context) if record.contexts.is_empty() => vec!["(none)".to_string()],
+            Some(UsageGrouping::Context) => record.contexts.clone(),
+        };
+        for key in keys {
+            groups.entry(key).or_default().add(record);
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: u64, model: &str, prompt: u64, completion: u64, cost: Option<f64>, contexts: &[&str]) -> UsageRecord {
+        UsageRecord {
+            timestamp,
+            model: model.to_string(),
+            usage: TokenUsage { prompt_tokens: prompt, completion_tokens: completion },
+            cost,
+            contexts: contexts.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_find_price_prefers_longest_match() {
+        let prices = default_price_table();
+        assert_eq!(find_price(&prices, "gpt-4o-mini-2024-07-18").unwrap().prompt_per_million, 0.15);
+        assert_eq!(find_price(&prices, "gpt-4o").unwrap().prompt_per_million, 2.5);
+        assert_eq!(find_price(&prices, "gpt-4-0613").unwrap().prompt_per_million, 30.0);
+        assert!(find_price(&prices, "llama3").is_none());
+    }
+
+    #[test]
+    fn test_cost() {
+        let price = ModelPrice { prompt_per_million: 30.0, completion_per_million: 60.0 };
+        let usage = TokenUsage { prompt_tokens: 1000, completion_tokens: 500 };
+        assert!((price.cost(&usage) - 0.06).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("7d"), Ok(7 * 24 * 60 * 60));
+        assert_eq!(parse_duration("12h"), Ok(12 * 60 * 60));
+        assert_eq!(parse_duration("2w"), Ok(14 * 24 * 60 * 60));
+        assert_eq!(parse_duration("30"), Err("Missing duration unit in '30', e.g. '30d' for days or '30h' for hours".to_string()));
+        assert!(parse_duration("7y").is_err());
+        assert!(parse_duration("d").is_err());
+        assert_eq!(parse_duration("99999999999999999w"), Err("Invalid duration: 99999999999999999w".to_string()));
+    }
+
+    #[test]
+    fn test_ledger_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = UsageLedger::new(dir.path().join("usage.jsonl"));
+        let first = record(1, "gpt-4", 10, 5, Some(0.1), &["git"]);
+        let second = record(2, "gpt-4o", 20, 10, None, &[]);
+
+        ledger.append(&first).unwrap();
+        ledger.append(&second).unwrap();
+
+        assert_eq!(ledger.read().unwrap(), vec![first, second]);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let records = vec![
+            record(10, "gpt-4", 100, 50, Some(1.0), &["git", "default"]),
+            record(20, "gpt-4o", 10, 5, None, &["default"]),
+            record(30, "gpt-4", 200, 100, Some(2.0), &[]),
+        ];
+
+        let total = summarize(&records, 0, None);
+        assert_eq!(total["total"].invocations, 3);
+        assert_eq!(total["total"].usage.prompt_tokens, 310);
+        assert_eq!(total["total"].cost, 3.0);
+        assert_eq!(total["total"].unpriced_invocations, 1);
+
+        let by_model = summarize(&records, 15, Some(UsageGrouping::Model));
+        assert_eq!(by_model["gpt-4"].invocations, 1);
+        assert_eq!(by_model["gpt-4o"].invocations, 1);
+
+        let by_context = summarize(&records, 0, Some(UsageGrouping::Context));
+        assert_eq!(by_context["default"].invocations, 2);
+        assert_eq!(by_context["git"].invocations, 1);
+        assert_eq!(by_context["(none)"].invocations, 1);
+    }
+}
@@ -0,0 +1,153 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const SCRIPT: &str = "ls -la";
+
+/// Answers every chat completion request with [SCRIPT].
+fn fake_api() -> String {
+    fake_api_recording().0
+}
+
+/// Like [fake_api], also returns the bodies of the requests.
+fn fake_api_recording() -> (String, Arc<Mutex<Vec<String>>>) {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v1", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut request = vec![0; content_length];
+            reader.read_exact(&mut request).unwrap();
+            recorded.lock().unwrap().push(String::from_utf8_lossy(&request).to_string());
+
+            let body = serde_json::json!({
+                "id": "x", "object": "chat.completion", "created": 1, "model": "gpt-4",
+                "choices": [{"index": 0, "message": {"role": "assistant", "content": SCRIPT}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 50, "completion_tokens": 10, "total_tokens": 60},
+            }).to_string();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                            Connection: close\r\n\r\n{}", body.len(), body).unwrap();
+        }
+    });
+    (url, requests)
+}
+
+/// `hu` with the given home directory, against the fake API.
+fn hu_command(home: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_hu"));
+    command
+        .args(args)
+        .current_dir(home)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("OPENAI_API_KEY", "sk-test")
+        .env("HOUSTON_OPEN_AI__BASE_URL", fake_api());
+    command
+}
+
+/// Runs `hu` with a fresh home directory against the fake API.
+fn hu(args: &[&str]) -> Output {
+    let home = tempfile::tempdir().unwrap();
+    hu_command(home.path(), args).output().unwrap()
+}
+
+#[test]
+fn test_print_only_puts_exactly_the_script_on_stdout() {
+    let output = hu(&["--print-only", "--", "list", "files"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}\n", SCRIPT));
+}
+
+#[test]
+fn test_instructions_can_start_with_a_subcommand_name() {
+    for instruction in ["init a git repo", "usage of the disk", "config the firewall", "fix the permissions of ~/.ssh"] {
+        let mut args = vec!["--print-only"];
+        args.extend(instruction.split(' '));
+
+        let output = hu(&args);
+
+        assert!(output.status.success(), "{}: {}", instruction, String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}\n", SCRIPT), "{}", instruction);
+    }
+}
+
+#[test]
+fn test_slow_input_on_stdin_is_waited_for() {
+    let home = tempfile::tempdir().unwrap();
+    let (url, requests) = fake_api_recording();
+    let mut child = hu_command(home.path(), &["--print-only", "explain", "the", "error"])
+        .env("HOUSTON_OPEN_AI__BASE_URL", url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    std::thread::sleep(Duration::from_millis(1500));
+    stdin.write_all(b"error: linker `cc` not found\n").unwrap();
+    drop(stdin);
+
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(requests.lock().unwrap().concat().contains("linker `cc` not found"));
+}
+
+#[test]
+fn test_no_stdin_skips_a_pipe_that_is_never_closed() {
+    let home = tempfile::tempdir().unwrap();
+    let mut child = hu_command(home.path(), &["--print-only", "list", "files"])
+        .env("HU_NO_STDIN", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the pipe stays open until the end of the test, without anything written to it
+    let _stdin = child.stdin.take();
+    let started = Instant::now();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}\n", SCRIPT));
+}
+
+#[test]
+fn test_print_only_asks_for_a_single_command_without_the_script_rules() {
+    let home = tempfile::tempdir().unwrap();
+    let (url, requests) = fake_api_recording();
+
+    let output = hu_command(home.path(), &["--print-only", "--shell", "bash", "list", "files"])
+        .env("HOUSTON_OPEN_AI__BASE_URL", url)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let request = requests.lock().unwrap().concat();
+    assert!(request.contains("a single command line"), "{}", request);
+    for rule in ["#!/usr/bin/env bash", "set -euo pipefail", "You are a Bash script generator"] {
+        assert!(!request.contains(rule), "{} in {}", rule, request);
+    }
+}